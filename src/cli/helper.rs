@@ -1,6 +1,6 @@
 use std::path::Path;
 
-use crate::{config, operation, prelude::*, system};
+use crate::{config, operation, prelude::*, state, system};
 
 /// Common terminate hook.
 pub(super) fn exit(prefer_code: Option<i32>) {
@@ -12,15 +12,36 @@ pub(super) fn exit(prefer_code: Option<i32>) {
 pub(super) async fn operation_chain(
     system: &mut system::System,
     config_dir: &Path,
+    profile: Option<String>,
+    state: &state::State,
 ) -> Result<(config::Config, operation::OperationChain)> {
-    let config = config::Config::load_from_dir(config_dir).await?;
+    let os = system::Api::os(&*system);
+    let mut config = config::Config::load_from_dir(config_dir, os).await?;
+    if let Some(profile) = profile {
+        config.select_profile(profile)?;
+    }
 
     debug!("load configuration from {}", config_dir.display());
     trace!("{:#?}", config);
 
-    let ops_chain = operation::plan(system, &config).await?;
+    let ops_chain = operation::plan(system, &config, state).await?;
 
     trace!("planed operations {:#?}", ops_chain);
 
     Ok((config, ops_chain))
 }
+
+pub(super) fn format_backup(backup: &state::Backup) -> String {
+    let mut msg = format!(
+        "[Backup {}]\n    Created: {}",
+        backup.id, backup.manifest.created_at
+    );
+    for entry in backup.manifest.entries.iter() {
+        let kind = match entry.kind {
+            state::BackupKind::File { .. } => "file",
+            state::BackupKind::SymbolicLink { .. } => "symlink",
+        };
+        msg.push_str(&format!("\n    {:>7}: {}", kind, entry.path.display()));
+    }
+    msg
+}
@@ -45,4 +45,5 @@ pub fn initialize_from_args() -> LocalEnv {
 pub enum SubCommand {
     Apply(subcommands::Apply),
     Plan(subcommands::Plan),
+    Restore(subcommands::Restore),
 }
@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use structopt::StructOpt;
 
-use crate::{cli::helper, operation, prelude::*, system::System};
+use crate::{cli::helper, operation, prelude::*, state, system::System};
 
 const APPLY_ABOUT: &str = "\
 about apply subcommand...
@@ -14,8 +14,29 @@ pub struct Apply {
     #[structopt(long = "dir", help = "configuration directory path to apply.")]
     pub config_dir_path: PathBuf, // NOTE: required or default current directory.
 
+    #[structopt(
+        long = "profile",
+        env = "LOCALENV_PROFILE",
+        help = "profile in configuration selecting entries to apply. every entry is applied if omitted."
+    )]
+    pub profile: Option<String>,
+
     #[structopt(long = "dry-run", help = "no changed will occur in dry run mode.")]
     pub dry_run: bool,
+
+    #[structopt(
+        long = "prune",
+        help = "remove files, symlinks and packages created by localenv but dropped from configuration."
+    )]
+    pub prune: bool,
+
+    #[structopt(
+        long = "jobs",
+        short = "j",
+        default_value = "1",
+        help = "number of commands installed concurrently. outputs are shown after all installs finish when greater than 1."
+    )]
+    pub jobs: usize,
 }
 
 pub async fn run(opt: Apply) {
@@ -28,19 +49,35 @@ pub async fn run(opt: Apply) {
 
 async fn apply(opt: Apply) -> Result<()> {
     let mut system = System::new();
-    let (config, mut ops_chain) =
-        helper::operation_chain(&mut system, opt.config_dir_path.as_path())
-            .await
-            .context("running apply")?;
+    let state_dir = state::state_dir()?;
+    let mut state = state::State::load(&system, &state_dir)?;
+    let (config, mut ops_chain) = helper::operation_chain(
+        &mut system,
+        opt.config_dir_path.as_path(),
+        opt.profile.clone(),
+        &state,
+    )
+    .await
+    .context("running apply")?;
+
+    let mut backup_store = state::BackupStore::new(&state_dir);
 
     operation::apply(operation::ApplyParam {
         system: &mut system,
         config: &config,
         operation_chain: &mut ops_chain,
+        backup_store: &mut backup_store,
+        state: &mut state,
         dry_run: opt.dry_run,
+        prune: opt.prune,
+        jobs: opt.jobs,
     })
     .await?;
 
+    if !opt.dry_run {
+        state.save(&mut system, &state_dir)?;
+    }
+
     operation::display(operation::DisplayParam {
         operation_chain: &ops_chain,
         system: &mut system,
@@ -48,5 +85,17 @@ async fn apply(opt: Apply) -> Result<()> {
     })
     .await?;
 
+    use crate::system::Api;
+    if let Some(dir) = backup_store.current_dir() {
+        system.display(format!("Overwritten entries saved to {}", dir.display()));
+    }
+    let removals = ops_chain.removal_count();
+    if removals > 0 && !opt.prune {
+        system.display(format!(
+            "{} entries dropped from configuration were kept. run apply with --prune to remove them.",
+            removals
+        ));
+    }
+
     Ok(())
 }
@@ -3,3 +3,6 @@ pub use apply::{run as run_apply, Apply};
 
 mod plan;
 pub use plan::{run as run_plan, Plan};
+
+mod restore;
+pub use restore::{run as run_restore, Restore};
@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use structopt::StructOpt;
 
-use crate::{cli::helper, operation, prelude::*, system::System};
+use crate::{cli::helper, operation, prelude::*, state, system::System};
 
 const PLAN_ABOUT: &str = "\
 about plan subcommand...
@@ -13,6 +13,13 @@ about plan subcommand...
 pub struct Plan {
     #[structopt(long = "dir", help = "configuration directory path to apply.")]
     pub config_dir_path: PathBuf, // NOTE: required or default current directory.
+
+    #[structopt(
+        long = "profile",
+        env = "LOCALENV_PROFILE",
+        help = "profile in configuration selecting entries to apply. every entry is applied if omitted."
+    )]
+    pub profile: Option<String>,
 }
 
 pub async fn run(opt: Plan) {
@@ -25,8 +32,14 @@ pub async fn run(opt: Plan) {
 
 async fn plan(opt: Plan) -> Result<()> {
     let mut system = System::new();
-    let (config, ops_chain) =
-        helper::operation_chain(&mut system, opt.config_dir_path.as_path()).await?;
+    let state = state::State::load(&system, state::state_dir()?)?;
+    let (config, ops_chain) = helper::operation_chain(
+        &mut system,
+        opt.config_dir_path.as_path(),
+        opt.profile,
+        &state,
+    )
+    .await?;
 
     operation::display(operation::DisplayParam {
         system: &mut system,
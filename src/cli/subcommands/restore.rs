@@ -0,0 +1,51 @@
+use structopt::StructOpt;
+
+use crate::{cli::helper, prelude::*, state, system::System};
+
+const RESTORE_ABOUT: &str = "\
+restore files and symlinks overwritten by apply. list backups if no id given.
+";
+
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(about = RESTORE_ABOUT)]
+pub struct Restore {
+    #[structopt(help = "backup id to restore.")]
+    pub backup_id: Option<String>,
+
+    #[structopt(long = "dry-run", help = "no changed will occur in dry run mode.")]
+    pub dry_run: bool,
+}
+
+pub async fn run(opt: Restore) {
+    // Validate opt if needed.
+    if let Err(err) = restore(opt).await {
+        error!("{}", err);
+        helper::exit(None);
+    }
+}
+
+async fn restore(opt: Restore) -> Result<()> {
+    use crate::system::Api;
+
+    let mut system = System::new();
+    let mut store = state::BackupStore::new(state::state_dir()?);
+
+    match opt.backup_id {
+        None => {
+            for backup in store.list(&system)? {
+                system.display(helper::format_backup(&backup));
+            }
+        }
+        Some(id) => {
+            let backup = store
+                .restore(&mut system, &id, opt.dry_run)
+                .context("running restore")?;
+            system.display(helper::format_backup(&backup));
+            if let Some(dir) = store.current_dir() {
+                system.display(format!("Overwritten entries saved to {}", dir.display()));
+            }
+        }
+    }
+
+    Ok(())
+}
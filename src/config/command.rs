@@ -1,5 +1,13 @@
 use serde::Deserialize;
 
+use std::{
+    cmp::Ordering,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::Condition, error::ErrorKind, prelude::*};
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Commands {
     pub cargo: Option<Vec<CargoCommand>>,
@@ -14,6 +22,53 @@ pub enum Command {
     Go(GoCommand),
 }
 
+impl Commands {
+    /// Id and dependencies of every command entry.
+    pub(super) fn dependencies(&self) -> impl Iterator<Item = (&str, &[String])> {
+        let cargo = self
+            .cargo
+            .iter()
+            .flatten()
+            .map(|c| (c.id(), c.depends_on()));
+        let brew = self.brew.iter().flatten().map(|c| (c.id(), c.depends_on()));
+        let go = self.go.iter().flatten().map(|c| (c.id(), c.depends_on()));
+        cargo.chain(brew).chain(go)
+    }
+
+    /// Reject options of command entries which installer can not honour.
+    pub(super) fn validate(&self) -> Result<()> {
+        for cmd in self.cargo.iter().flatten() {
+            if let Some(reason) = cmd.conflict() {
+                return Err(Error::from(ErrorKind::InvalidCommandEntry {
+                    id: cmd.id().to_owned(),
+                    reason: reason.to_owned(),
+                }));
+            }
+        }
+        for cmd in self.brew.iter().flatten() {
+            if let Some(version) = &cmd.version {
+                return Err(Error::from(ErrorKind::InvalidCommandEntry {
+                    id: cmd.id().to_owned(),
+                    reason: format!(
+                        "version {} is not supported by brew. use versioned formula like name@version",
+                        version
+                    ),
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve relative paths in command entries against config directory.
+    pub(super) fn resolve_paths(&mut self, root_dir: &Path) {
+        for cmd in self.cargo.iter_mut().flatten() {
+            if let Some(path) = cmd.path.as_mut() {
+                *path = root_dir.join(&path);
+            }
+        }
+    }
+}
+
 impl Command {
     pub fn bin(&self) -> &str {
         match &self {
@@ -22,33 +77,219 @@ impl Command {
             Command::Go(cmd) => cmd.bin(),
         }
     }
+
+    pub fn package(&self) -> &str {
+        match &self {
+            Command::Cargo(cmd) => cmd.package(),
+            Command::Brew(cmd) => cmd.package(),
+            Command::Go(cmd) => cmd.package(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        self.base().id()
+    }
+
+    /// Directory installed into. None for installer's default.
+    pub fn root(&self) -> Option<&Path> {
+        match &self {
+            Command::Cargo(cmd) => cmd.root.as_deref(),
+            Command::Brew(_) | Command::Go(_) => None,
+        }
+    }
+
+    pub fn depends_on(&self) -> &[String] {
+        self.base().depends_on.as_slice()
+    }
+}
+
+/// Command entry of any installer. Gives access to fields shared by every entry.
+pub trait CommandEntry {
+    fn base(&self) -> &CommandBase;
+}
+
+impl CommandEntry for Command {
+    fn base(&self) -> &CommandBase {
+        match &self {
+            Command::Cargo(cmd) => &cmd.base,
+            Command::Brew(cmd) => &cmd.base,
+            Command::Go(cmd) => &cmd.base,
+        }
+    }
+}
+
+impl CommandEntry for GoCommand {
+    fn base(&self) -> &CommandBase {
+        &self.base
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct CommandBase {
+    /// Referenced by `depends_on` of other entries. Defaults to package.
+    id: Option<String>,
     package: String,
     bin: Option<String>,
+    /// Ids of entries which must be applied before this command.
+    #[serde(default)]
+    depends_on: Vec<String>,
+    /// Used by profiles to select entries.
+    #[serde(default)]
+    tags: Vec<String>,
+    condition: Option<Condition>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct CargoCommand {
     #[serde(flatten)]
     base: CommandBase,
+    /// Exact version or semver requirement.
+    #[serde(rename(deserialize = "version"))]
+    pub raw_version: Option<String>,
+    #[serde(default)]
+    pub features: Vec<String>,
+    #[serde(default)]
+    pub all_features: bool,
+    #[serde(default)]
+    pub no_default_features: bool,
+    /// Build with Cargo.lock of the package.
+    #[serde(default)]
+    pub locked: bool,
+    /// Git repository url to install from.
+    pub git: Option<String>,
+    pub branch: Option<String>,
+    pub rev: Option<String>,
+    /// Local crate to install from. Relative to config directory.
+    pub path: Option<PathBuf>,
+    /// Directory to install into instead of cargo home.
+    pub root: Option<PathBuf>,
+}
+
+impl CargoCommand {
+    /// Bin explicitly configured. None if it defaults to package name.
+    pub fn configured_bin(&self) -> Option<&str> {
+        self.base.bin.as_deref()
+    }
+
+    /// Combination of source options `cargo install` does not accept.
+    fn conflict(&self) -> Option<&'static str> {
+        match (&self.git, &self.path) {
+            (Some(_), Some(_)) => Some("git and path are exclusive"),
+            (Some(_), None) | (None, Some(_)) if self.raw_version.is_some() => {
+                Some("version is only for packages from crates.io, not git or path")
+            }
+            (None, _) if self.branch.is_some() || self.rev.is_some() => {
+                Some("branch and rev require git")
+            }
+            (Some(_), None) if self.branch.is_some() && self.rev.is_some() => {
+                Some("branch and rev are exclusive")
+            }
+            _ => None,
+        }
+    }
+
+    pub fn version_requirement(&self) -> Result<Option<VersionRequirement>> {
+        self.raw_version
+            .as_deref()
+            .map(|raw| VersionRequirement::parse(self.package(), raw))
+            .transpose()
+    }
+
+    /// Source and features. e.g. `https://github.com/ymgyt/localenv (branch main) (features: a,b)`
+    pub fn description(&self) -> String {
+        let source = match (&self.git, &self.path) {
+            (Some(git), _) => match (&self.branch, &self.rev) {
+                (_, Some(rev)) => format!("{}#{}", git, rev),
+                (Some(branch), None) => format!("{} (branch {})", git, branch),
+                (None, None) => git.clone(),
+            },
+            (None, Some(path)) => path.display().to_string(),
+            (None, None) => "crates.io".to_owned(),
+        };
+
+        let mut options = Vec::new();
+        if !self.features.is_empty() {
+            options.push(format!("features: {}", self.features.join(",")));
+        }
+        if self.all_features {
+            options.push("all features".to_owned());
+        }
+        if self.no_default_features {
+            options.push("no default features".to_owned());
+        }
+
+        if options.is_empty() {
+            source
+        } else {
+            format!("{} ({})", source, options.join(", "))
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct BrewCommand {
     #[serde(flatten)]
     base: CommandBase,
+    /// Install as cask instead of formula.
+    #[serde(default)]
+    pub cask: bool,
+    /// brew always installs latest formula, so version is rejected on load.
+    version: Option<String>,
+}
+
+impl BrewCommand {
+    /// Package name without tap prefix. e.g. `homebrew/core/git` -> `git`
+    pub fn formula_name(&self) -> &str {
+        self.package().rsplit('/').next().unwrap_or_default()
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GoCommand {
     #[serde(flatten)]
     base: CommandBase,
+    /// Module version passed to `go install`. latest if omitted.
+    /// Installed binary is replaced only when exact version like `v0.9.3` differs from it.
+    pub version: Option<String>,
+}
+
+impl GoCommand {
+    /// Binary name defaults to last element of module path.
+    /// e.g. `github.com/ahmetb/kubectx/cmd/kubectx` -> `kubectx`
+    pub fn bin(&self) -> &str {
+        match self.base.bin {
+            Some(ref bin) => bin,
+            None => self.package().rsplit('/').next().unwrap_or_default(),
+        }
+    }
+
+    pub fn package(&self) -> &str {
+        self.base.package()
+    }
+
+    pub fn id(&self) -> &str {
+        self.base.id()
+    }
+
+    pub fn depends_on(&self) -> &[String] {
+        self.base.depends_on.as_slice()
+    }
+
+    /// Exact module version pinned by `version`. e.g. `v0.9.3`
+    /// None for `latest`, branches and commits, which can not be compared with installed binary.
+    pub fn version_requirement(&self) -> Option<VersionRequirement> {
+        let version = self.version.as_deref()?.strip_prefix('v')?;
+        VersionRequirement::parse(self.package(), version)
+            .ok()
+            .filter(|req| req.exact().is_some())
+    }
 }
 
 impl CommandBase {
+    pub fn id(&self) -> &str {
+        self.id.as_deref().unwrap_or(&self.package)
+    }
+
     pub fn bin(&self) -> &str {
         if let Some(ref bin) = self.bin {
             bin
@@ -60,6 +301,90 @@ impl CommandBase {
     pub fn package(&self) -> &str {
         &self.package
     }
+
+    pub fn tags(&self) -> &[String] {
+        self.tags.as_slice()
+    }
+
+    pub fn condition(&self) -> Option<&Condition> {
+        self.condition.as_ref()
+    }
+}
+
+/// Version of command entry. Plain version like `0.18.0` means exact version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionRequirement {
+    raw: String,
+    req: semver::VersionReq,
+    exact: Option<semver::Version>,
+}
+
+impl VersionRequirement {
+    pub fn parse(package: &str, raw: &str) -> Result<Self> {
+        let raw = raw.trim();
+        let invalid = || {
+            Error::from(ErrorKind::InvalidVersionRequirement {
+                package: package.to_owned(),
+                raw: raw.to_owned(),
+            })
+        };
+
+        match semver::Version::parse(raw) {
+            Ok(exact) => Ok(Self {
+                raw: raw.to_owned(),
+                req: semver::VersionReq::exact(&exact),
+                exact: Some(exact),
+            }),
+            Err(_) => Ok(Self {
+                raw: raw.to_owned(),
+                req: semver::VersionReq::parse(raw).map_err(|_| invalid())?,
+                exact: None,
+            }),
+        }
+    }
+
+    pub fn matches(&self, version: &semver::Version) -> bool {
+        self.req.matches(version)
+    }
+
+    pub fn exact(&self) -> Option<&semver::Version> {
+        self.exact.as_ref()
+    }
+
+    /// Whether satisfying the requirement means going up from installed version.
+    /// Requirement is compared by its first bound. e.g. `>=0.18, <0.20` -> `0.18.0`
+    pub fn is_upgrade_from(&self, installed: &semver::Version) -> bool {
+        let bound = match &self.exact {
+            Some(exact) => Some(exact.clone()),
+            None => first_bound(&self.raw),
+        };
+        match bound {
+            Some(bound) => installed.cmp(&bound) == Ordering::Less,
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for VersionRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+/// First version appearing in requirement with omitted parts and wildcards filled with 0.
+fn first_bound(raw: &str) -> Option<semver::Version> {
+    let first = raw.split(',').next()?;
+    let version = first.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let mut parts = version
+        .trim()
+        .split('.')
+        .map(|part| part.parse::<u64>().unwrap_or(0))
+        .chain(std::iter::repeat(0));
+    Some(semver::Version::new(
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+    ))
 }
 
 macro_rules! delegate_base {
@@ -71,10 +396,105 @@ macro_rules! delegate_base {
             pub fn package(&self) -> &str {
                 self.base.package()
             }
+            pub fn id(&self) -> &str {
+                self.base.id()
+            }
+            pub fn depends_on(&self) -> &[String] {
+                self.base.depends_on.as_slice()
+            }
+        }
+
+        impl CommandEntry for $c {
+            fn base(&self) -> &CommandBase {
+                &self.base
+            }
         }
     };
 }
 
 delegate_base!(CargoCommand);
 delegate_base!(BrewCommand);
-delegate_base!(GoCommand);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_requirement() {
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+
+        let exact = VersionRequirement::parse("bat", "0.18.0").unwrap();
+        assert_eq!(exact.exact(), Some(&v("0.18.0")));
+        assert!(exact.matches(&v("0.18.0")));
+        assert!(!exact.matches(&v("0.18.1")));
+        assert!(exact.is_upgrade_from(&v("0.17.1")));
+        assert!(!exact.is_upgrade_from(&v("0.18.1")));
+
+        let req = VersionRequirement::parse("bat", ">=0.18, <0.20").unwrap();
+        assert_eq!(req.exact(), None);
+        assert!(req.matches(&v("0.19.3")));
+        assert!(req.is_upgrade_from(&v("0.17.1")));
+        assert!(!req.is_upgrade_from(&v("0.20.0")));
+
+        assert!(VersionRequirement::parse("bat", "^0.18")
+            .unwrap()
+            .is_upgrade_from(&v("0.17.1")));
+        assert!(VersionRequirement::parse("bat", "latest").is_err());
+    }
+
+    #[test]
+    fn reject_conflicting_cargo_source() {
+        let conflict = |yaml: &str| {
+            serde_yaml::from_str::<CargoCommand>(yaml)
+                .unwrap()
+                .conflict()
+        };
+
+        assert_eq!(conflict("{ package: bat, version: 0.18.0 }"), None);
+        assert_eq!(
+            conflict("{ package: bat, git: https://github.com/sharkdp/bat, rev: abc }"),
+            None
+        );
+        assert!(
+            conflict("{ package: bat, git: https://github.com/sharkdp/bat, path: bat }").is_some()
+        );
+        assert!(conflict("{ package: bat, version: 0.18.0, path: bat }").is_some());
+        assert!(
+            conflict("{ package: bat, version: 0.18.0, git: https://github.com/sharkdp/bat }")
+                .is_some()
+        );
+        assert!(conflict("{ package: bat, branch: main }").is_some());
+        assert!(conflict(
+            "{ package: bat, git: https://github.com/sharkdp/bat, branch: main, rev: abc }"
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn reject_brew_version() {
+        let commands: Commands =
+            serde_yaml::from_str("brew:\n  - { package: git, version: 2.31.1 }").unwrap();
+        assert!(matches!(
+            commands.validate().unwrap_err().kind(),
+            ErrorKind::InvalidCommandEntry { id, .. } if id == "git"
+        ));
+    }
+
+    #[test]
+    fn go_version_requirement() {
+        let requirement = |version: &str| {
+            serde_yaml::from_str::<GoCommand>(&format!(
+                "{{ package: github.com/x/y, version: {} }}",
+                version
+            ))
+            .unwrap()
+            .version_requirement()
+            .map(|req| req.to_string())
+        };
+
+        assert_eq!(requirement("v0.9.3"), Some("0.9.3".to_owned()));
+        assert_eq!(requirement("latest"), None);
+        assert_eq!(requirement("main"), None);
+        assert_eq!(requirement("v0.9"), None);
+    }
+}
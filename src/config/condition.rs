@@ -0,0 +1,103 @@
+use serde::Deserialize;
+
+use std::{fmt, path::PathBuf};
+
+use crate::system::Os;
+
+/// Condition under which entry is applied. Every given field must be satisfied.
+/// Unknown fields are rejected, since misspelled field would make condition always satisfied.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    pub os: Option<Os>,
+    pub hostname: Option<String>,
+    pub username: Option<String>,
+    /// Architecture localenv binary is built for (`std::env::consts::ARCH`) like `x86_64` or `aarch64`.
+    /// Not detected from running CPU, so x86_64 binary under Rosetta matches `x86_64`.
+    pub arch: Option<String>,
+    /// `ID` in `/etc/os-release` like `ubuntu`.
+    pub distro: Option<String>,
+    pub env: Option<EnvCondition>,
+    pub path_exists: Option<PathBuf>,
+    /// Binary found in $PATH.
+    pub binary: Option<String>,
+    #[serde(default)]
+    pub all: Vec<Condition>,
+    /// Satisfied if any of conditions is. Ignored if empty.
+    #[serde(default)]
+    pub any: Vec<Condition>,
+    pub not: Option<Box<Condition>>,
+}
+
+/// Env var is defined, and has value if given.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EnvCondition {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// Describe what condition requires. e.g. `os is mac, username is alice`
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(os) = self.os {
+            parts.push(format!("os is {}", os));
+        }
+        if let Some(ref hostname) = self.hostname {
+            parts.push(format!("hostname is {}", hostname));
+        }
+        if let Some(ref username) = self.username {
+            parts.push(format!("username is {}", username));
+        }
+        if let Some(ref arch) = self.arch {
+            parts.push(format!("arch is {}", arch));
+        }
+        if let Some(ref distro) = self.distro {
+            parts.push(format!("distro is {}", distro));
+        }
+        if let Some(ref env) = self.env {
+            match env.value {
+                Some(ref value) => parts.push(format!("env {} is {}", env.name, value)),
+                None => parts.push(format!("env {} is defined", env.name)),
+            }
+        }
+        if let Some(ref path) = self.path_exists {
+            parts.push(format!("path {} exists", path.display()));
+        }
+        if let Some(ref binary) = self.binary {
+            parts.push(format!("binary {} found in $PATH", binary));
+        }
+        if !self.all.is_empty() {
+            let all = self.all.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+            parts.push(format!("all of ({})", all.join("; ")));
+        }
+        if !self.any.is_empty() {
+            let any = self.any.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+            parts.push(format!("any of ({})", any.join("; ")));
+        }
+        if let Some(ref not) = self.not {
+            parts.push(format!("not ({})", not));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_unknown_field() {
+        assert!(serde_yaml::from_str::<Condition>("{ hostname: work, os: mac }").is_ok());
+
+        let err = serde_yaml::from_str::<Condition>("{ hostnmae: work }").unwrap_err();
+        assert!(
+            err.to_string().contains("unknown field `hostnmae`"),
+            "{}",
+            err
+        );
+        assert!(serde_yaml::from_str::<Condition>("any: [{ distribution: ubuntu }]").is_err());
+        assert!(serde_yaml::from_str::<Condition>("env: { name: WORK, vaule: '1' }").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+use crate::{config::Spec, error::ErrorKind, prelude::*};
+
+/// Check ids referenced by `depends_on` exist and dependencies between entries form no cycle.
+pub(super) fn validate(spec: &Spec) -> Result<()> {
+    // ids are unique, checked by `include::validate` with files defining them.
+    let graph: HashMap<&str, &[String]> = spec
+        .filesystem
+        .entries
+        .iter()
+        .filter_map(|entry| entry.id().map(|id| (id, entry.depends_on())))
+        .chain(spec.commands.dependencies())
+        .collect();
+
+    // entries without id can still depend on others.
+    let anonymous = spec
+        .filesystem
+        .entries
+        .iter()
+        .filter(|entry| entry.id().is_none())
+        .map(|entry| (entry.description(), entry.depends_on()));
+    for (entry, depends_on) in graph
+        .iter()
+        .map(|(id, depends_on)| (*id, *depends_on))
+        .chain(anonymous)
+    {
+        if let Some(dependency) = depends_on
+            .iter()
+            .find(|dependency| !graph.contains_key(dependency.as_str()))
+        {
+            return Err(Error::from(ErrorKind::UnknownDependency {
+                entry: entry.to_owned(),
+                dependency: dependency.clone(),
+            }));
+        }
+    }
+
+    let mut ids = graph.keys().copied().collect::<Vec<_>>();
+    ids.sort_unstable();
+    let mut visited = HashSet::new();
+    let dependencies = |id: &str| {
+        graph
+            .get(id)
+            .copied()
+            .unwrap_or_default()
+            .iter()
+            .map(String::as_str)
+    };
+    for id in ids {
+        if let Some(cycle) = find_cycle(&dependencies, id, &mut Vec::new(), &mut visited) {
+            return Err(Error::from(ErrorKind::DependencyCycle {
+                entries: cycle.into_iter().map(str::to_owned).collect(),
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+/// Depth first search from given node following its dependencies.
+/// Returns nodes on cycle, starting and ending with same node.
+pub(crate) fn find_cycle<N, F, I>(
+    dependencies: &F,
+    node: N,
+    path: &mut Vec<N>,
+    visited: &mut HashSet<N>,
+) -> Option<Vec<N>>
+where
+    N: Copy + Eq + Hash,
+    F: Fn(N) -> I,
+    I: IntoIterator<Item = N>,
+{
+    if let Some(start) = path.iter().position(|p| *p == node) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(node);
+        return Some(cycle);
+    }
+    if !visited.insert(node) {
+        return None;
+    }
+
+    path.push(node);
+    for dependency in dependencies(node) {
+        if let Some(cycle) = find_cycle(dependencies, dependency, path, visited) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(yaml: &str) -> Spec {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn validate_yaml(commands: &str, entries: &str) -> Result<()> {
+        validate(&spec(&format!(
+            "localenv: '1'\nrequired_envs: []\ncommands:\n{}\nfilesystem:\n  entries:\n{}",
+            commands, entries
+        )))
+    }
+
+    #[test]
+    fn validate_dependencies() {
+        let entries = r"
+    - file:
+        id: starship-config
+        description: starship config.
+        env_base: HOME
+        relative_path: .config/starship.toml
+        content_from: static/starship.toml
+        mode: 0644
+        depends_on: [starship]
+    - symlink:
+        description: starship config link.
+        original_env_base: HOME
+        original_relative_path: .config/starship.toml
+        link_env_base: HOME
+        link_relative_path: .starship.toml
+        depends_on: [starship-config]";
+
+        assert!(validate_yaml("  cargo:\n    - { package: starship }", entries).is_ok());
+
+        assert!(matches!(
+            validate_yaml("  cargo:\n    - { package: exa }", entries)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::UnknownDependency { entry, dependency }
+                if entry == "starship-config" && dependency == "starship"
+        ));
+
+        match validate_yaml(
+            "  cargo:\n    - { package: starship, depends_on: [starship-config] }",
+            entries,
+        )
+        .unwrap_err()
+        .kind()
+        {
+            ErrorKind::DependencyCycle { entries } => {
+                assert_eq!(entries, &["starship", "starship-config", "starship"])
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
@@ -5,17 +5,29 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::{
-    error::ErrorKind,
-    prelude::*,
-    system::{FilePermission, Os},
-};
+use crate::{config::Condition, error::ErrorKind, prelude::*, system::FilePermission};
+
+const DEFAULT_DIRECTORY_MODE: &str = "755";
 
 #[derive(Deserialize, Debug)]
 pub struct Filesystem {
+    /// Mode of intermediate directories created for files and symlinks.
+    #[serde(rename(deserialize = "default_directory_mode"))]
+    pub raw_default_directory_mode: Option<String>,
     pub entries: Vec<FilesystemEntry>,
 }
 
+impl Filesystem {
+    #[cfg(target_family = "unix")]
+    pub fn default_directory_permission(&self) -> Result<FilePermission> {
+        parse_permission(
+            self.raw_default_directory_mode
+                .as_deref()
+                .unwrap_or(DEFAULT_DIRECTORY_MODE),
+        )
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub enum FilesystemEntry {
     #[serde(rename = "symlink")]
@@ -27,7 +39,7 @@ pub enum FilesystemEntry {
 }
 
 impl FilesystemEntry {
-    pub fn condition(&self) -> Option<&FilesystemEntryCondition> {
+    pub fn condition(&self) -> Option<&Condition> {
         match self {
             FilesystemEntry::SymbolicLink(entry) => entry.base.condition.as_ref(),
             FilesystemEntry::File(entry) => entry.base.condition.as_ref(),
@@ -35,23 +47,46 @@ impl FilesystemEntry {
         }
     }
     pub fn description(&self) -> &str {
+        self.base().description.as_str()
+    }
+    pub fn id(&self) -> Option<&str> {
+        self.base().id.as_deref()
+    }
+    pub fn depends_on(&self) -> &[String] {
+        self.base().depends_on.as_slice()
+    }
+    pub fn tags(&self) -> &[String] {
+        self.base().tags.as_slice()
+    }
+    fn base(&self) -> &FilesystemEntryBase {
+        match self {
+            FilesystemEntry::SymbolicLink(entry) => &entry.base,
+            FilesystemEntry::File(entry) => &entry.base,
+            FilesystemEntry::Directory(entry) => &entry.base,
+        }
+    }
+    /// Path of the entry localenv writes.
+    pub fn path(&self) -> Result<PathBuf> {
         match self {
-            FilesystemEntry::SymbolicLink(entry) => entry.base.description.as_str(),
-            FilesystemEntry::File(entry) => entry.base.description.as_str(),
-            FilesystemEntry::Directory(entry) => entry.base.description.as_str(),
+            FilesystemEntry::SymbolicLink(entry) => entry.link_path(),
+            FilesystemEntry::File(entry) => entry.dest_path(),
+            FilesystemEntry::Directory(entry) => entry.path(),
         }
     }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct FilesystemEntryBase {
+    /// Referenced by `depends_on` of other entries.
+    pub id: Option<String>,
     pub description: String,
-    pub condition: Option<FilesystemEntryCondition>,
-}
-
-#[derive(Deserialize, Debug, Clone)]
-pub struct FilesystemEntryCondition {
-    pub os: Option<Os>,
+    pub condition: Option<Condition>,
+    /// Used by profiles to select entries.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Ids of entries which must be applied before this entry.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -65,24 +100,22 @@ pub struct SymlinkEntry {
 }
 
 impl SymlinkEntry {
-    pub fn original_path(&self) -> PathBuf {
-        let key = self
-            .original_env_base
-            .as_ref()
-            .expect("original env base required");
-        Path::new(&env::var_os(key).expect("env undefined")).join(
-            self.original_relative_path
-                .as_ref()
-                .expect("original_relative_path"),
+    pub fn original_path(&self) -> Result<PathBuf> {
+        resolve_path(
+            self.description(),
+            ("original_env_base", self.original_env_base.as_deref()),
+            (
+                "original_relative_path",
+                self.original_relative_path.as_deref(),
+            ),
         )
     }
 
-    pub fn link_path(&self) -> PathBuf {
-        let key = self.link_env_base.as_ref().expect("link env base required");
-        Path::new(&env::var_os(key).expect("env undefined")).join(
-            self.link_relative_path
-                .as_ref()
-                .expect("link_relative_path"),
+    pub fn link_path(&self) -> Result<PathBuf> {
+        resolve_path(
+            self.description(),
+            ("link_env_base", self.link_env_base.as_deref()),
+            ("link_relative_path", self.link_relative_path.as_deref()),
         )
     }
 
@@ -98,18 +131,20 @@ pub struct FileEntry {
     pub env_base: Option<String>,
     pub relative_path: Option<String>,
     pub content_from: PathBuf,
+    /// Render content as template before writing.
+    #[serde(default)]
+    pub template: bool,
     #[serde(rename(deserialize = "mode"))]
     pub raw_mode: String,
 }
 
 impl FileEntry {
-    pub fn dest_path(&self) -> PathBuf {
-        if let Some(key) = &self.env_base {
-            Path::new(&env::var_os(key).expect("env undefined"))
-                .join(self.relative_path.as_ref().expect("relative_path"))
-        } else {
-            unimplemented!("expect env_base")
-        }
+    pub fn dest_path(&self) -> Result<PathBuf> {
+        resolve_path(
+            self.description(),
+            ("env_base", self.env_base.as_deref()),
+            ("relative_path", self.relative_path.as_deref()),
+        )
     }
 
     pub fn src_path(&self, root: impl AsRef<Path>) -> PathBuf {
@@ -122,21 +157,76 @@ impl FileEntry {
 
     #[cfg(target_family = "unix")]
     pub fn permission(&self) -> Result<FilePermission> {
-        u32::from_str_radix(&self.raw_mode, 8)
-            .map_err(|_| {
-                ErrorKind::InvalidFilePermission {
-                    raw: self.raw_mode.clone(),
-                }
-                .into()
-            })
-            .map(FilePermission::UnixMode)
+        parse_permission(&self.raw_mode)
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct DirectoryEntry {
     #[serde(flatten)]
     pub base: FilesystemEntryBase,
+    pub env_base: Option<String>,
+    pub relative_path: Option<String>,
+    #[serde(rename(deserialize = "mode"))]
+    pub raw_mode: String,
+    /// Create missing parent directories too.
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+impl DirectoryEntry {
+    pub fn path(&self) -> Result<PathBuf> {
+        resolve_path(
+            self.description(),
+            ("env_base", self.env_base.as_deref()),
+            ("relative_path", self.relative_path.as_deref()),
+        )
+    }
+
+    pub fn description(&self) -> &str {
+        self.base.description.as_str()
+    }
+
+    #[cfg(target_family = "unix")]
+    pub fn permission(&self) -> Result<FilePermission> {
+        parse_permission(&self.raw_mode)
+    }
+}
+
+/// Join relative path to value of env base. Fields are given with their names for error message.
+fn resolve_path(
+    entry: &str,
+    (env_field, env_base): (&'static str, Option<&str>),
+    (relative_field, relative_path): (&'static str, Option<&str>),
+) -> Result<PathBuf> {
+    let missing = |field| {
+        Error::from(ErrorKind::EntryFieldMissing {
+            entry: entry.to_owned(),
+            field,
+        })
+    };
+    let env_base = env_base.ok_or_else(|| missing(env_field))?;
+    let relative_path = relative_path.ok_or_else(|| missing(relative_field))?;
+    let base = env::var_os(env_base).ok_or_else(|| {
+        Error::from(ErrorKind::EnvUndefined {
+            entry: entry.to_owned(),
+            env: env_base.to_owned(),
+        })
+    })?;
+
+    Ok(Path::new(&base).join(relative_path))
+}
+
+#[cfg(target_family = "unix")]
+fn parse_permission(raw_mode: &str) -> Result<FilePermission> {
+    u32::from_str_radix(raw_mode, 8)
+        .map_err(|_| {
+            ErrorKind::InvalidFilePermission {
+                raw: raw_mode.to_owned(),
+            }
+            .into()
+        })
+        .map(FilePermission::UnixMode)
 }
 
 #[cfg(test)]
@@ -146,7 +236,7 @@ mod tests {
     #[test]
     #[cfg(target_family = "unix")]
     fn file_entry_mode() {
-        for tc in vec![
+        for tc in &[
             // input, expect
             ("666", 0o666),
             ("0666", 0o666),
@@ -162,15 +252,74 @@ mod tests {
         }
     }
 
+    #[test]
+    fn deserialize_directory_entry() {
+        let entry: FilesystemEntry = serde_yaml::from_str(
+            r"
+directory:
+  description: local scripts.
+  env_base: HOME
+  relative_path: .local/bin
+  mode: 0700
+",
+        )
+        .unwrap();
+
+        match entry {
+            FilesystemEntry::Directory(dir) => {
+                assert_eq!(dir.permission().ok(), Some(FilePermission::UnixMode(0o700)));
+                assert!(!dir.recursive);
+            }
+            _ => panic!("expect directory entry"),
+        }
+    }
+
+    #[test]
+    fn resolve_entry_path() {
+        let sym: SymlinkEntry = serde_yaml::from_str(
+            r"
+description: broken link.
+original_env_base: HOME
+original_relative_path: x
+link_env_base: LOCALENV_TEST_UNDEFINED
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            sym.original_path().ok(),
+            Some(Path::new(&env::var_os("HOME").unwrap()).join("x"))
+        );
+        assert!(matches!(
+            sym.link_path().unwrap_err().kind(),
+            ErrorKind::EntryFieldMissing { entry, field }
+                if entry == "broken link." && *field == "link_relative_path"
+        ));
+
+        let mut file = file_entry();
+        file.base.description = "undefined env.".to_owned();
+        file.env_base = Some("LOCALENV_TEST_UNDEFINED".to_owned());
+        file.relative_path = Some("x".to_owned());
+        assert!(matches!(
+            file.dest_path().unwrap_err().kind(),
+            ErrorKind::EnvUndefined { entry, env }
+                if entry == "undefined env." && env == "LOCALENV_TEST_UNDEFINED"
+        ));
+    }
+
     fn file_entry() -> FileEntry {
         FileEntry {
             base: FilesystemEntryBase {
+                id: None,
                 description: String::new(),
                 condition: None,
+                tags: Vec::new(),
+                depends_on: Vec::new(),
             },
             env_base: None,
             relative_path: None,
             content_from: PathBuf::new(),
+            template: false,
             raw_mode: String::new(),
         }
     }
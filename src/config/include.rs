@@ -0,0 +1,505 @@
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use tokio::fs;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{interpolation, Commands, Filesystem, Lang, Profile, RequiredEnvEntry, Spec},
+    error::ErrorKind,
+    prelude::*,
+    state,
+    system::Os,
+};
+
+const INCLUDE_KEY: &str = "include";
+
+/// Sequences concatenated across config files. Items are traced back to their file by position.
+const ENTRY_SECTIONS: [[&str; 2]; 4] = [
+    ["commands", "cargo"],
+    ["commands", "brew"],
+    ["commands", "go"],
+    ["filesystem", "entries"],
+];
+
+/// Config file or fragment merged into config.
+struct Source {
+    path: PathBuf,
+    content: Vec<u8>,
+    /// Number of items in each of `ENTRY_SECTIONS`.
+    entries: [usize; 4],
+}
+
+impl Source {
+    fn new(path: PathBuf, content: Vec<u8>, value: &Value) -> Self {
+        let mut entries = [0; 4];
+        for (count, [section, key]) in entries.iter_mut().zip(ENTRY_SECTIONS.iter()) {
+            *count = value
+                .get(section)
+                .and_then(|section| section.get(key))
+                .and_then(Value::as_sequence)
+                .map_or(0, Vec::len);
+        }
+        Self {
+            path,
+            content,
+            entries,
+        }
+    }
+}
+
+/// Files config is loaded from. Config file comes first, then fragments in merge order.
+pub(super) struct Sources(Vec<Source>);
+
+impl Sources {
+    /// Hash of content of every file.
+    pub(super) fn revision(&self) -> String {
+        let content = self
+            .0
+            .iter()
+            .flat_map(|source| source.content.iter().copied())
+            .collect::<Vec<_>>();
+        state::content_hash(&content)
+    }
+
+    /// File which defines item at index of merged section.
+    fn origin(&self, section: usize, mut index: usize) -> &Path {
+        for source in self.0.iter() {
+            if index < source.entries[section] {
+                return source.path.as_path();
+            }
+            index -= source.entries[section];
+        }
+        self.0[0].path.as_path()
+    }
+}
+
+/// Parse config file and merge fragments matched by its `include` patterns.
+/// Patterns are relative to config directory and matched files are merged in order.
+/// Each file is interpolated and checked against config schema before merge, so that
+/// errors point to the file defining the value.
+/// Sequences are concatenated, mappings are merged and other values must be defined only once.
+pub(super) async fn load(
+    dir: &Path,
+    path: PathBuf,
+    content: Vec<u8>,
+    os: Os,
+) -> Result<(Value, Sources)> {
+    let mut config = parse(&path, &content)?;
+    let patterns = match &mut config {
+        Value::Mapping(mapping) => mapping.remove(&Value::from(INCLUDE_KEY)),
+        _ => None,
+    };
+    let patterns = match patterns {
+        Some(patterns) => serde_yaml::from_value::<Vec<String>>(patterns).map_err(|yaml_err| {
+            ErrorKind::ConfigFileParseFailed {
+                yaml_err,
+                path: path.clone(),
+            }
+        })?,
+        None => Vec::new(),
+    };
+
+    let mut files = vec![(path, content, config)];
+    for pattern in patterns {
+        for fragment_path in expand(dir, &pattern)? {
+            if files.iter().any(|(path, _, _)| *path == fragment_path) {
+                continue;
+            }
+            debug!(path = %(fragment_path.display()), "loading config fragment");
+
+            let content = fs::read(&fragment_path).await.map_err(|io_err| {
+                Error::from(ErrorKind::ConfigFileReadFailed {
+                    io_err,
+                    path: fragment_path.clone(),
+                })
+            })?;
+            let fragment = parse(&fragment_path, &content)?;
+            if fragment.get(INCLUDE_KEY).is_some() {
+                return Err(Error::from(ErrorKind::InvalidInclude {
+                    pattern,
+                    reason: format!("{} includes other files", fragment_path.display()),
+                }));
+            }
+            files.push((fragment_path, content, fragment));
+        }
+    }
+
+    interpolation::interpolate(
+        files
+            .iter_mut()
+            .map(|(path, _, value)| (path.as_path(), value)),
+        os,
+    )?;
+
+    let mut config = Value::Null;
+    let mut sources = Vec::with_capacity(files.len());
+    for (path, content, value) in files {
+        check_schema(&path, &value)?;
+        let source = Source::new(path, content, &value);
+        merge(&mut config, value, &source.path, &mut Vec::new())?;
+        sources.push(source);
+    }
+
+    Ok((config, Sources(sources)))
+}
+
+/// Deserialize each section of config file into its type.
+/// Sections defined in other files are not required.
+fn check_schema(path: &Path, config: &Value) -> Result<()> {
+    fn check<T: DeserializeOwned>(path: &Path, section: &Value) -> Result<()> {
+        serde_yaml::from_value::<T>(section.clone())
+            .map(|_| ())
+            .map_err(|yaml_err| {
+                Error::from(ErrorKind::ConfigFileParseFailed {
+                    yaml_err,
+                    path: path.to_path_buf(),
+                })
+            })
+    }
+
+    let sections = match config {
+        Value::Mapping(sections) => sections,
+        _ => return check::<Spec>(path, config),
+    };
+    for (key, section) in sections {
+        match key.as_str().unwrap_or_default() {
+            "localenv" => check::<String>(path, section)?,
+            "required_envs" => check::<Vec<RequiredEnvEntry>>(path, section)?,
+            "commands" => check::<Commands>(path, section)?,
+            "lang" => check::<Lang>(path, section)?,
+            "filesystem" => {
+                // fragment may only set options without entries.
+                let mut section = section.clone();
+                if let Value::Mapping(filesystem) = &mut section {
+                    let entries = Value::from("entries");
+                    if !filesystem.contains_key(&entries) {
+                        filesystem.insert(entries, Value::Sequence(Vec::new()));
+                    }
+                }
+                check::<Filesystem>(path, &section)?
+            }
+            "profiles" => check::<BTreeMap<String, Profile>>(path, section)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Check no entry id or filesystem path is defined twice, reporting files which define it.
+/// Filesystem entries with condition or tags may share path, so that conditions or profiles
+/// can choose between alternative files.
+pub(super) fn validate(spec: &Spec, sources: &Sources) -> Result<()> {
+    let commands = &spec.commands;
+    // indexed by position in each of `ENTRY_SECTIONS`. filesystem entries may have no id.
+    let ids: [Vec<Option<&str>>; 4] = [
+        commands
+            .cargo
+            .iter()
+            .flatten()
+            .map(|c| Some(c.id()))
+            .collect(),
+        commands
+            .brew
+            .iter()
+            .flatten()
+            .map(|c| Some(c.id()))
+            .collect(),
+        commands.go.iter().flatten().map(|c| Some(c.id())).collect(),
+        spec.filesystem.entries.iter().map(|e| e.id()).collect(),
+    ];
+    let mut defined = HashMap::new();
+    for (section, ids) in ids.iter().enumerate() {
+        for (index, id) in ids.iter().enumerate() {
+            let id = match id {
+                Some(id) => *id,
+                None => continue,
+            };
+            if let Some((s, i)) = defined.insert(id, (section, index)) {
+                return Err(duplicate(sources, id.to_owned(), (s, i), (section, index)));
+            }
+        }
+    }
+
+    let section = ENTRY_SECTIONS.len() - 1;
+    let mut defined = HashMap::new();
+    for (index, entry) in spec.filesystem.entries.iter().enumerate() {
+        if entry.condition().is_some() || !entry.tags().is_empty() {
+            continue;
+        }
+        let path = entry.path()?;
+        if let Some(i) = defined.insert(path.clone(), index) {
+            let entry = path.display().to_string();
+            return Err(duplicate(sources, entry, (section, i), (section, index)));
+        }
+    }
+
+    Ok(())
+}
+
+fn duplicate(
+    sources: &Sources,
+    entry: String,
+    first: (usize, usize),
+    second: (usize, usize),
+) -> Error {
+    Error::from(ErrorKind::DuplicateEntry {
+        entry,
+        files: vec![
+            sources.origin(first.0, first.1).to_path_buf(),
+            sources.origin(second.0, second.1).to_path_buf(),
+        ],
+    })
+}
+
+fn parse(path: &Path, content: &[u8]) -> Result<Value> {
+    serde_yaml::from_slice(content).map_err(|yaml_err| {
+        Error::from(ErrorKind::ConfigFileParseFailed {
+            yaml_err,
+            path: path.to_path_buf(),
+        })
+    })
+}
+
+/// Files matching pattern relative to dir, sorted by path.
+fn expand(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let invalid = |reason: String| {
+        Error::from(ErrorKind::InvalidInclude {
+            pattern: pattern.to_owned(),
+            reason,
+        })
+    };
+    let mut paths = glob::glob(&dir.join(pattern).to_string_lossy())
+        .map_err(|err| invalid(err.to_string()))?
+        .filter(|path| !matches!(path, Ok(path) if path.is_dir()))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|err| invalid(err.to_string()))?;
+    if paths.is_empty() {
+        return Err(invalid("no file matched".to_owned()));
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Merge fragment into config. `keys` holds keys from top level to current value.
+fn merge(config: &mut Value, fragment: Value, path: &Path, keys: &mut Vec<String>) -> Result<()> {
+    match (config, fragment) {
+        (_, Value::Null) => (),
+        (config @ Value::Null, fragment) => *config = fragment,
+        (Value::Mapping(config), Value::Mapping(fragment)) => {
+            for (key, value) in fragment {
+                match config.get_mut(&key) {
+                    Some(current) => {
+                        keys.push(key.as_str().unwrap_or_default().to_owned());
+                        merge(current, value, path, keys)?;
+                        keys.pop();
+                    }
+                    None => {
+                        config.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Sequence(config), Value::Sequence(fragment)) => config.extend(fragment),
+        _ => {
+            return Err(Error::from(ErrorKind::ConfigMergeConflict {
+                path: path.to_path_buf(),
+                key: keys.join("."),
+            }));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Directory removed when dropped, so that fragments are cleaned up even if test fails.
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn write_fragments(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir =
+            std::env::temp_dir().join(format!("localenv-include-{}-{}", name, std::process::id()));
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        TempDir(dir)
+    }
+
+    async fn load_spec(dir: &Path, config: &str) -> Result<(Spec, Sources)> {
+        let (value, sources) =
+            load(dir, dir.join("localenv.yaml"), config.into(), Os::Linux).await?;
+        let spec = serde_yaml::from_value::<Spec>(value).unwrap();
+        validate(&spec, &sources).map(|_| (spec, sources))
+    }
+
+    #[tokio::test]
+    async fn merge_fragments() {
+        let tmp = write_fragments(
+            "merge",
+            &[
+                (
+                    "commands/cargo.yaml",
+                    "commands:\n  cargo:\n    - { package: exa }",
+                ),
+                (
+                    "commands/go.yaml",
+                    "vars: { editor: nvim }\ncommands:\n  go:\n    - { package: github.com/x/y, bin: y }",
+                ),
+                (
+                    "fs/shell.yaml",
+                    "filesystem:\n  entries:\n    - directory: { description: bin, env_base: HOME, relative_path: bin, mode: 0755 }",
+                ),
+                (
+                    "dup/cargo.yaml",
+                    "commands:\n  cargo:\n    - { package: bat }",
+                ),
+                ("conflict/fs.yaml", "filesystem:\n  default_directory_mode: 0700"),
+                (
+                    "invalid/cargo.yaml",
+                    "commands:\n  cargo:\n    - { bin: bat }",
+                ),
+                ("invalid/vars.yaml", "vars: { pager: '${no_such_var}' }"),
+                (
+                    "dup/fs.yaml",
+                    "filesystem:\n  entries:\n    - directory: { description: bin again, env_base: HOME, relative_path: bin, mode: 0700 }",
+                ),
+                (
+                    "dup/id.yaml",
+                    "filesystem:\n  entries:\n    - directory: { id: bat, description: bat dir, env_base: HOME, relative_path: bat, mode: 0755 }",
+                ),
+                (
+                    "profile/home.yaml",
+                    "filesystem:\n  entries:\n    - file: { description: home zshrc, env_base: HOME, relative_path: .zshrc, content_from: static/zshrc.home, mode: 0644, tags: [home] }",
+                ),
+                (
+                    "profile/work.yaml",
+                    "filesystem:\n  entries:\n    - file: { description: work zshrc, env_base: HOME, relative_path: .zshrc, content_from: static/zshrc.work, mode: 0644, tags: [work] }",
+                ),
+            ],
+        );
+        let dir = tmp.0.as_path();
+        std::fs::create_dir_all(dir.join("broken")).unwrap();
+        std::os::unix::fs::symlink(dir.join("no-such-file"), dir.join("broken/cargo.yaml"))
+            .unwrap();
+        let config = r"
+localenv: '1'
+include: [commands/*.yaml, fs/shell.yaml, commands/cargo.yaml]
+required_envs: []
+commands:
+  cargo:
+    - { package: bat }
+filesystem:
+  default_directory_mode: 0755
+  entries: []
+";
+        let (spec, sources) = load_spec(dir, config).await.unwrap();
+        let cargo = spec.commands.cargo.as_ref().unwrap();
+        assert_eq!(
+            cargo.iter().map(|c| c.package()).collect::<Vec<_>>(),
+            ["bat", "exa"]
+        );
+        assert_eq!(spec.commands.go.as_ref().unwrap().len(), 1);
+        assert_eq!(spec.filesystem.entries.len(), 1);
+        assert_eq!(spec.vars["editor"].as_str(), Some("nvim"));
+        assert_eq!(sources.origin(0, 1), dir.join("commands/cargo.yaml"));
+
+        match load_spec(dir, &config.replace("fs/shell.yaml", "dup/*.yaml"))
+            .await
+            .err()
+            .unwrap()
+            .kind()
+        {
+            ErrorKind::DuplicateEntry { entry, files } => {
+                assert_eq!(entry, "bat");
+                assert_eq!(
+                    files,
+                    &[dir.join("localenv.yaml"), dir.join("dup/cargo.yaml")]
+                );
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+
+        assert!(matches!(
+            load_spec(dir, &config.replace("fs/shell.yaml", "fs/shell.yaml, dup/fs.yaml"))
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::DuplicateEntry { files, .. }
+                if files == &[dir.join("fs/shell.yaml"), dir.join("dup/fs.yaml")]
+        ));
+        // ids are shared by commands and filesystem entries.
+        assert!(matches!(
+            load_spec(dir, &config.replace("fs/shell.yaml", "dup/id.yaml"))
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::DuplicateEntry { entry, files }
+                if entry == "bat" && files == &[dir.join("localenv.yaml"), dir.join("dup/id.yaml")]
+        ));
+        // tagged variants of same file are selected by profiles.
+        let (spec, _) = load_spec(dir, &config.replace("fs/shell.yaml", "profile/*.yaml"))
+            .await
+            .unwrap();
+        assert_eq!(spec.filesystem.entries.len(), 2);
+
+        assert!(matches!(
+            load_spec(dir, &config.replace("fs/shell.yaml", "conflict/fs.yaml"))
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::ConfigMergeConflict { path, key }
+                if path == &dir.join("conflict/fs.yaml") && key == "filesystem.default_directory_mode"
+        ));
+
+        assert!(matches!(
+            load_spec(dir, &config.replace("fs/shell.yaml", "no/such/*.yaml"))
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::InvalidInclude { pattern, .. } if pattern == "no/such/*.yaml"
+        ));
+
+        // read, schema and interpolation errors point to fragment.
+        assert!(matches!(
+            load_spec(dir, &config.replace("fs/shell.yaml", "broken/*.yaml"))
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::ConfigFileReadFailed { path, .. } if path == &dir.join("broken/cargo.yaml")
+        ));
+        assert!(matches!(
+            load_spec(dir, &config.replace("fs/shell.yaml", "invalid/cargo.yaml"))
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::ConfigFileParseFailed { path, .. } if path == &dir.join("invalid/cargo.yaml")
+        ));
+        assert!(matches!(
+            load_spec(dir, &config.replace("fs/shell.yaml", "invalid/vars.yaml"))
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::VarUndefined { path, .. } if path == &dir.join("invalid/vars.yaml")
+        ));
+    }
+}
@@ -0,0 +1,284 @@
+use serde_yaml::{Mapping, Value};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, mem,
+    path::Path,
+};
+
+use crate::{error::ErrorKind, prelude::*, system::Os};
+
+const VARS_KEY: &str = "vars";
+
+/// Resolve `vars` defined across config files, then substitute `${var}`, `${env:NAME}` and `${os}`
+/// in every string of each file. `$${` is kept as literal `${`.
+/// Errors report file whose string or var could not be resolved.
+pub(super) fn interpolate<'a, I>(files: I, os: Os) -> Result<()>
+where
+    I: IntoIterator<Item = (&'a Path, &'a mut Value)>,
+{
+    let mut files = files.into_iter().collect::<Vec<_>>();
+    let mut raw = BTreeMap::new();
+    for (path, config) in files.iter() {
+        if let Some(Value::Mapping(vars)) = config.get(VARS_KEY) {
+            for (name, value) in vars.iter() {
+                let name = name.as_str().unwrap_or_default();
+                if raw
+                    .insert(name.to_owned(), (*path, value.clone()))
+                    .is_some()
+                {
+                    return Err(Error::from(ErrorKind::ConfigMergeConflict {
+                        path: path.to_path_buf(),
+                        key: format!("{}.{}", VARS_KEY, name),
+                    }));
+                }
+            }
+        }
+    }
+    let mut resolver = Resolver {
+        raw,
+        resolved: HashMap::new(),
+        os,
+        path: Path::new(""),
+    };
+
+    // resolve in name order so that first error is stable.
+    let names = resolver.raw.keys().cloned().collect::<Vec<_>>();
+    for name in names {
+        resolver.var(&name, &mut Vec::new())?;
+    }
+
+    for (path, config) in files.iter_mut() {
+        resolver.path = path;
+        if let Value::Mapping(config) = config {
+            for (key, value) in config.iter_mut() {
+                if key.as_str() != Some(VARS_KEY) {
+                    resolver.substitute_value(value, &mut Vec::new())?;
+                    continue;
+                }
+                if let Value::Mapping(vars) = value {
+                    let resolved = vars
+                        .iter()
+                        .filter_map(|(name, _)| {
+                            let value = resolver.resolved.get(name.as_str()?)?;
+                            Some((name.clone(), value.clone()))
+                        })
+                        .collect::<Mapping>();
+                    *vars = resolved;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Resolver<'a> {
+    /// Vars with file defining them.
+    raw: BTreeMap<String, (&'a Path, Value)>,
+    resolved: HashMap<String, Value>,
+    os: Os,
+    /// File whose strings are being substituted.
+    path: &'a Path,
+}
+
+impl<'a> Resolver<'a> {
+    /// Return value of reference as string. `stack` holds vars being resolved.
+    fn reference(&mut self, reference: &str, stack: &mut Vec<String>) -> Result<String> {
+        if let Some(name) = reference.strip_prefix("env:") {
+            return env::var(name).map_err(|_| {
+                Error::from(ErrorKind::VarUndefined {
+                    var: reference.to_owned(),
+                    path: self.path.to_path_buf(),
+                })
+            });
+        }
+        if !self.raw.contains_key(reference) && reference == "os" {
+            return Ok(self.os.to_string());
+        }
+
+        match self.var(reference, stack)? {
+            Value::String(s) => Ok(s),
+            Value::Number(n) => Ok(n.to_string()),
+            Value::Bool(b) => Ok(b.to_string()),
+            _ => Err(Error::from(ErrorKind::InvalidInterpolation {
+                reason: format!("${{{}}} is not a scalar", reference),
+                path: self.path.to_path_buf(),
+            })),
+        }
+    }
+
+    /// Resolve var defined in `vars`.
+    fn var(&mut self, name: &str, stack: &mut Vec<String>) -> Result<Value> {
+        if let Some(value) = self.resolved.get(name) {
+            return Ok(value.clone());
+        }
+        if let Some(start) = stack.iter().position(|var| var == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name.to_owned());
+            return Err(Error::from(ErrorKind::VarCycle {
+                vars: cycle,
+                path: self.path.to_path_buf(),
+            }));
+        }
+        let (origin, mut value) = match self.raw.get(name) {
+            Some((origin, value)) => (*origin, value.clone()),
+            None => {
+                return Err(Error::from(ErrorKind::VarUndefined {
+                    var: name.to_owned(),
+                    path: self.path.to_path_buf(),
+                }))
+            }
+        };
+
+        // errors in value of var are reported with file defining it.
+        stack.push(name.to_owned());
+        let path = mem::replace(&mut self.path, origin);
+        let substituted = self.substitute_value(&mut value, stack);
+        self.path = path;
+        substituted?;
+        stack.pop();
+
+        self.resolved.insert(name.to_owned(), value.clone());
+        Ok(value)
+    }
+
+    fn substitute_value(&mut self, value: &mut Value, stack: &mut Vec<String>) -> Result<()> {
+        match value {
+            Value::String(s) => *s = self.substitute(s, stack)?,
+            Value::Sequence(seq) => {
+                for value in seq.iter_mut() {
+                    self.substitute_value(value, stack)?;
+                }
+            }
+            Value::Mapping(mapping) => {
+                for (_, value) in mapping.iter_mut() {
+                    self.substitute_value(value, stack)?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn substitute(&mut self, raw: &str, stack: &mut Vec<String>) -> Result<String> {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(start) = rest.find('$') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(escaped) = rest.strip_prefix("$${") {
+                out.push_str("${");
+                rest = escaped;
+            } else if let Some(reference) = rest.strip_prefix("${") {
+                let end = reference.find('}').ok_or_else(|| {
+                    Error::from(ErrorKind::InvalidInterpolation {
+                        reason: format!("missing closing brace in {}", raw),
+                        path: self.path.to_path_buf(),
+                    })
+                })?;
+                out.push_str(&self.reference(&reference[..end], stack)?);
+                rest = &reference[end + 1..];
+            } else {
+                out.push('$');
+                rest = &rest[1..];
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interpolate_yaml(yaml: &str) -> Result<Value> {
+        let mut value = serde_yaml::from_str(yaml).unwrap();
+        interpolate(vec![(Path::new("localenv.yaml"), &mut value)], Os::Linux).map(|_| value)
+    }
+
+    #[test]
+    fn interpolate_config() {
+        let value = interpolate_yaml(
+            r"
+vars:
+  config_dir: .config/${app}
+  app: nu
+  plugins: [git, '${app}']
+filesystem:
+  entries:
+    - file:
+        description: ${app} config for ${os}, ${env:HOME}. $${literal} $5
+        relative_path: ${config_dir}/config.toml
+        mode: 0644
+",
+        )
+        .unwrap();
+        assert_eq!(value["vars"]["config_dir"].as_str(), Some(".config/nu"));
+        assert_eq!(value["vars"]["plugins"][1].as_str(), Some("nu"));
+        let file = &value["filesystem"]["entries"][0]["file"];
+        assert_eq!(
+            file["description"].as_str().unwrap(),
+            format!(
+                "nu config for linux, {}. ${{literal}} $5",
+                env::var("HOME").unwrap()
+            )
+        );
+        assert_eq!(
+            file["relative_path"].as_str(),
+            Some(".config/nu/config.toml")
+        );
+
+        assert!(matches!(
+            interpolate_yaml("description: ${no_such_var}").unwrap_err().kind(),
+            ErrorKind::VarUndefined { var, .. } if var == "no_such_var"
+        ));
+        assert!(matches!(
+            interpolate_yaml("description: ${env:LOCALENV_TEST_UNDEFINED}").unwrap_err().kind(),
+            ErrorKind::VarUndefined { var, .. } if var == "env:LOCALENV_TEST_UNDEFINED"
+        ));
+        assert!(matches!(
+            interpolate_yaml("vars: { list: [a] }\ndescription: ${list}")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidInterpolation { .. }
+        ));
+        match interpolate_yaml("vars: { a: '${b}', b: 'x${c}', c: '${a}' }")
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::VarCycle { vars, .. } => assert_eq!(vars, &["a", "b", "c", "a"]),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn interpolate_across_files() {
+        let parse = |yaml: &str| serde_yaml::from_str::<Value>(yaml).unwrap();
+        let (root, fragment) = (Path::new("localenv.yaml"), Path::new("vars.yaml"));
+
+        let mut config = parse("description: ${app}");
+        let mut vars = parse("vars: { app: nu }");
+        interpolate(vec![(root, &mut config), (fragment, &mut vars)], Os::Linux).unwrap();
+        assert_eq!(config["description"].as_str(), Some("nu"));
+
+        // error in var is reported with file defining it.
+        let mut vars = parse("vars: { app: '${no_such_var}' }");
+        assert!(matches!(
+            interpolate(vec![(root, &mut config), (fragment, &mut vars)], Os::Linux)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::VarUndefined { path, .. } if path == fragment
+        ));
+
+        let mut config = parse("vars: { app: nu }");
+        let mut vars = parse("vars: { app: bat }");
+        assert!(matches!(
+            interpolate(vec![(root, &mut config), (fragment, &mut vars)], Os::Linux)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ConfigMergeConflict { path, key } if path == fragment && key == "vars.app"
+        ));
+    }
+}
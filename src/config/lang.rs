@@ -0,0 +1,21 @@
+use serde::Deserialize;
+
+use std::path::{Path, PathBuf};
+
+/// Language toolchain settings.
+#[derive(Deserialize, Debug, Default)]
+pub struct Lang {
+    pub go: Option<GoLang>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct GoLang {
+    /// Go installation whose `bin/go` installs go commands.
+    pub goroot: Option<PathBuf>,
+}
+
+impl Lang {
+    pub fn goroot(&self) -> Option<&Path> {
+        self.go.as_ref().and_then(|go| go.goroot.as_deref())
+    }
+}
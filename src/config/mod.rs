@@ -1,25 +1,51 @@
 mod filesystem;
-pub use filesystem::{FileEntry, Filesystem, FilesystemEntry, SymlinkEntry};
+pub use filesystem::{
+    DirectoryEntry, FileEntry, Filesystem, FilesystemEntry, FilesystemEntryBase, SymlinkEntry,
+};
 
 mod command;
-pub use command::{CargoCommand, Command, Commands};
+pub use command::{
+    BrewCommand, CargoCommand, Command, CommandEntry, Commands, GoCommand, VersionRequirement,
+};
+
+mod lang;
+pub use lang::Lang;
+
+mod condition;
+pub use condition::Condition;
+
+mod profile;
+pub use profile::Profile;
+
+mod dependency;
+pub(crate) use dependency::find_cycle;
+mod include;
+mod interpolation;
+mod required_env;
 
 use serde::Deserialize;
 use tokio::fs;
 
 use std::{
+    collections::BTreeMap,
     io,
     path::{Path, PathBuf},
 };
 
-use crate::prelude::*;
+use crate::{prelude::*, system::Os};
 
 const DEFAULT_CONFIG_FILE: &str = "localenv.yaml";
+/// Value of `localenv` key this binary understands.
+pub const SUPPORTED_VERSION: &str = "1";
 
 #[derive(Debug)]
 pub struct Config {
     pub spec: Spec,
     pub root_dir: PathBuf,
+    /// Hash of config file content.
+    pub revision: String,
+    /// Name of profile selecting entries to apply. Every entry is applied if None.
+    pub profile: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -28,8 +54,15 @@ pub struct Spec {
     pub version: String,
 
     pub required_envs: Vec<RequiredEnvEntry>,
+    /// Variables referenced by `${}` in config and available to file templates.
+    #[serde(default)]
+    pub vars: BTreeMap<String, serde_yaml::Value>,
     pub commands: Commands,
+    #[serde(default)]
+    pub lang: Lang,
     pub filesystem: Filesystem,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -39,18 +72,19 @@ pub struct RequiredEnvEntry {
 }
 
 impl Config {
-    /// Load configuration from given dir.
-    pub async fn load_from_dir(path: impl AsRef<Path>) -> Result<Self> {
-        let dir_path = path
-            .as_ref()
-            .to_path_buf()
-            .canonicalize()
-            .expect("invalid path");
+    /// Load configuration from given dir. os is referenced by `${os}` in config.
+    pub async fn load_from_dir(path: impl AsRef<Path>, os: Os) -> Result<Self> {
+        let dir_path = path.as_ref().canonicalize().map_err(|io_err| {
+            Error::from(ErrorKind::InvalidConfigDir {
+                path: path.as_ref().to_path_buf(),
+                io_err,
+            })
+        })?;
         let config_path = dir_path.join(DEFAULT_CONFIG_FILE);
 
         debug!(path = %(config_path.display()), "loading config file");
 
-        let f = fs::File::open(&config_path)
+        let content = fs::read(&config_path)
             .await
             .map_err(|io_err| {
                 Error::from(match io_err.kind() {
@@ -66,18 +100,87 @@ impl Config {
                 DEFAULT_CONFIG_FILE
             ))?;
 
-        let spec = serde_yaml::from_reader::<_, Spec>(f.into_std().await).map_err(|e| {
+        let (value, sources) = include::load(&dir_path, config_path.clone(), content, os).await?;
+        let mut spec = serde_yaml::from_value::<Spec>(value).map_err(|yaml_err| {
             ErrorKind::ConfigFileParseFailed {
-                yaml_err: e,
+                yaml_err,
                 path: config_path,
             }
         })?;
+        if spec.version != SUPPORTED_VERSION {
+            return Err(Error::from(ErrorKind::UnsupportedConfigVersion {
+                version: spec.version,
+            }));
+        }
+
+        spec.commands.validate()?;
+        spec.commands.resolve_paths(&dir_path);
+        required_env::validate(&spec)?;
+        include::validate(&spec, &sources)?;
+        dependency::validate(&spec)?;
 
         let cfg = Self {
             spec,
             root_dir: dir_path,
+            revision: sources.revision(),
+            profile: None,
         };
 
         Ok(cfg)
     }
+
+    /// Select entries to apply with profile defined in `profiles`.
+    pub fn select_profile(&mut self, name: impl Into<String>) -> Result<()> {
+        let name = name.into();
+        if !self.spec.profiles.contains_key(&name) {
+            return Err(Error::from(ErrorKind::ProfileNotFound {
+                name,
+                defined: self.spec.profiles.keys().cloned().collect(),
+            }));
+        }
+        self.profile = Some(name);
+        Ok(())
+    }
+
+    /// Name and definition of selected profile.
+    pub fn active_profile(&self) -> Option<(&str, &Profile)> {
+        let name = self.profile.as_deref()?;
+        self.spec.profiles.get(name).map(|profile| (name, profile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn load_from_invalid_dir() {
+        let err = Config::load_from_dir("/no/such/localenv/config", Os::Linux)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::InvalidConfigDir { path, .. } if path == Path::new("/no/such/localenv/config")
+        ));
+    }
+
+    #[tokio::test]
+    async fn select_profile() {
+        let mut config = Config::load_from_dir(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config_example"),
+            Os::Linux,
+        )
+        .await
+        .unwrap();
+        config.select_profile("work").unwrap();
+        let (name, profile) = config.active_profile().unwrap();
+        assert_eq!(name, "work");
+        assert_eq!(profile.excluded_tag(&["gui".to_owned()]), Some("gui"));
+
+        assert!(matches!(
+            config.select_profile("no-such-profile").unwrap_err().kind(),
+            ErrorKind::ProfileNotFound { name, .. } if name == "no-such-profile"
+        ));
+        assert_eq!(config.profile.as_deref(), Some("work"));
+    }
 }
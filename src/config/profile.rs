@@ -0,0 +1,29 @@
+use serde::Deserialize;
+
+/// Subset of entries applied on a kind of machine. Entries are selected by their tags.
+/// Entries without tags are always selected.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Profile {
+    /// Tagged entries are selected only if they have one of these tags. Every tag is included if empty.
+    #[serde(default)]
+    pub include_tags: Vec<String>,
+    /// Entries with any of these tags are not selected.
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+}
+
+impl Profile {
+    /// First tag of entry excluded by this profile.
+    pub fn excluded_tag<'a>(&self, tags: &'a [String]) -> Option<&'a str> {
+        tags.iter()
+            .find(|tag| self.exclude_tags.contains(tag))
+            .map(String::as_str)
+    }
+
+    /// Whether entry with given tags passes `include_tags`.
+    pub fn includes(&self, tags: &[String]) -> bool {
+        tags.is_empty()
+            || self.include_tags.is_empty()
+            || tags.iter().any(|tag| self.include_tags.contains(tag))
+    }
+}
@@ -0,0 +1,106 @@
+use std::{collections::HashSet, env};
+
+use crate::{
+    config::{FilesystemEntry, Spec},
+    error::ErrorKind,
+    prelude::*,
+};
+
+/// Check every env referenced by filesystem entries is declared in `required_envs`,
+/// then check every required env is defined.
+pub(super) fn validate(spec: &Spec) -> Result<()> {
+    let declared = spec
+        .required_envs
+        .iter()
+        .map(|env| env.name.as_str())
+        .collect::<HashSet<_>>();
+    for entry in spec.filesystem.entries.iter() {
+        if let Some(env) = env_bases(entry).find(|env| !declared.contains(env)) {
+            return Err(Error::from(ErrorKind::UndeclaredEnv {
+                entry: entry.description().to_owned(),
+                env: env.to_owned(),
+            }));
+        }
+    }
+
+    let undefined = spec
+        .required_envs
+        .iter()
+        .filter(|env| env::var_os(&env.name).is_none())
+        .map(|env| (env.name.clone(), env.description.clone()))
+        .collect::<Vec<_>>();
+    if !undefined.is_empty() {
+        return Err(Error::from(ErrorKind::RequiredEnvUndefined {
+            envs: undefined,
+        }));
+    }
+
+    Ok(())
+}
+
+fn env_bases(entry: &FilesystemEntry) -> impl Iterator<Item = &str> {
+    let envs = match entry {
+        FilesystemEntry::File(file) => vec![file.env_base.as_deref()],
+        FilesystemEntry::Directory(dir) => vec![dir.env_base.as_deref()],
+        FilesystemEntry::SymbolicLink(sym) => vec![
+            sym.original_env_base.as_deref(),
+            sym.link_env_base.as_deref(),
+        ],
+    };
+    envs.into_iter().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate_yaml(required_envs: &str, entries: &str) -> Result<()> {
+        let spec: Spec = serde_yaml::from_str(&format!(
+            "localenv: '1'\nrequired_envs:\n{}\ncommands: {{}}\nfilesystem:\n  entries:\n{}",
+            required_envs, entries
+        ))
+        .unwrap();
+        validate(&spec)
+    }
+
+    #[test]
+    fn validate_required_envs() {
+        let entries = r"
+    - symlink:
+        description: link to home.
+        original_env_base: HOME
+        original_relative_path: x
+        link_env_base: LOCALENV_TEST_LINK_DIR
+        link_relative_path: x";
+
+        match validate_yaml(
+            r"
+  - { name: HOME, description: home }
+  - { name: LOCALENV_TEST_LINK_DIR, description: link dir }
+  - { name: LOCALENV_TEST_OTHER, description: other }",
+            entries,
+        )
+        .unwrap_err()
+        .kind()
+        {
+            ErrorKind::RequiredEnvUndefined { envs } => assert_eq!(
+                envs,
+                &[
+                    ("LOCALENV_TEST_LINK_DIR".to_owned(), "link dir".to_owned()),
+                    ("LOCALENV_TEST_OTHER".to_owned(), "other".to_owned()),
+                ]
+            ),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+
+        assert!(matches!(
+            validate_yaml("  - { name: HOME, description: home }", entries)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::UndeclaredEnv { entry, env }
+                if entry == "link to home." && env == "LOCALENV_TEST_LINK_DIR"
+        ));
+
+        assert!(validate_yaml("  - { name: HOME, description: home }", "    []").is_ok());
+    }
+}
@@ -1,6 +1,6 @@
 use backtrace::Backtrace;
 
-use std::{error, ffi::OsString, fmt, io, path::PathBuf};
+use std::{error, ffi::OsString, fmt, io, path::PathBuf, time::Duration};
 
 pub trait ErrorContext {
     fn context(self, msg: impl Into<String>) -> Self;
@@ -11,8 +11,7 @@ impl<T> ErrorContext for Result<T, Error> {
         if let Err(ref mut err) = self {
             let trace = match Backtrace::new()
                 .frames()
-                .iter()
-                .nth(5)
+                .get(5)
                 .and_then(|frame| frame.symbols().first())
                 .map(|symbol| (symbol.filename().map(|p| p.to_path_buf()), symbol.lineno()))
             {
@@ -61,20 +60,147 @@ pub enum ErrorKind {
     ConfigFileNotFound {
         path: PathBuf,
     },
+    /// Version declared by `localenv` key is not supported.
+    UnsupportedConfigVersion {
+        version: String,
+    },
     /// Failed to parse config file.
     ConfigFileParseFailed {
         yaml_err: serde_yaml::Error,
         path: PathBuf,
     },
+    /// Failed to read config file or fragment.
+    ConfigFileReadFailed {
+        io_err: io::Error,
+        path: PathBuf,
+    },
+    /// Include pattern is invalid or matches no file.
+    InvalidInclude {
+        pattern: String,
+        reason: String,
+    },
+    /// Value in config fragment is already defined by other file, or differs in type.
+    ConfigMergeConflict {
+        path: PathBuf,
+        /// Dot separated keys from top level.
+        key: String,
+    },
+    /// Entry is defined more than once.
+    DuplicateEntry {
+        entry: String,
+        /// Files which define entry.
+        files: Vec<PathBuf>,
+    },
+    /// Config directory cannot be resolved.
+    InvalidConfigDir {
+        path: PathBuf,
+        io_err: io::Error,
+    },
+    /// Entry lacks field required to resolve its path.
+    EntryFieldMissing {
+        entry: String,
+        field: &'static str,
+    },
+    /// Env referenced by entry is not defined.
+    EnvUndefined {
+        entry: String,
+        env: String,
+    },
+    /// Variable referenced by `${}` is not defined.
+    VarUndefined {
+        var: String,
+        path: PathBuf,
+    },
+    /// Variables in `vars` reference each other.
+    VarCycle {
+        vars: Vec<String>,
+        path: PathBuf,
+    },
+    /// Malformed `${}` reference.
+    InvalidInterpolation {
+        reason: String,
+        path: PathBuf,
+    },
+    /// Profile is not defined in `profiles`.
+    ProfileNotFound {
+        name: String,
+        defined: Vec<String>,
+    },
     /// Invalid file permission.
     InvalidFilePermission {
         raw: String,
     },
+    /// Command entry has options which can not be honoured.
+    InvalidCommandEntry {
+        id: String,
+        reason: String,
+    },
+    /// Invalid version requirement of command entry.
+    InvalidVersionRequirement {
+        package: String,
+        raw: String,
+    },
+    /// Envs in `required_envs` are not defined. Pairs of name and description.
+    RequiredEnvUndefined {
+        envs: Vec<(String, String)>,
+    },
+    /// Entry references env not declared in `required_envs`.
+    UndeclaredEnv {
+        entry: String,
+        env: String,
+    },
+    /// Entry depends on id no entry has.
+    UnknownDependency {
+        entry: String,
+        dependency: String,
+    },
+    /// Entries depend on each other.
+    DependencyCycle {
+        entries: Vec<String>,
+    },
+    /// Operation skipped because operation it depends on failed.
+    DependencyFailed {
+        dependency: String,
+    },
+    /// Failed to render template of file entry.
+    TemplateRenderFailed {
+        path: PathBuf,
+        /// Line in template where error occurred, if known.
+        line: Option<usize>,
+        message: String,
+    },
     /// Command not found in $PATH.
     CommandNotFound {
         name: OsString,
         which_err: which::Error,
     },
+    /// Command exited with non-zero status.
+    CommandFailed {
+        name: OsString,
+        /// None if terminated by signal.
+        code: Option<i32>,
+    },
+    /// Command did not exit within timeout.
+    CommandTimeout {
+        name: OsString,
+        timeout: Duration,
+    },
+    /// None of env vars to locate state directory is defined.
+    StateDirUndefined,
+    /// Failed to parse file under state directory.
+    StateFileParseFailed {
+        yaml_err: serde_yaml::Error,
+        path: PathBuf,
+    },
+    /// State file was written by localenv of other state format.
+    UnsupportedStateVersion {
+        version: u32,
+        path: PathBuf,
+    },
+    /// Backup with given id does not exist.
+    BackupNotFound {
+        id: String,
+    },
     /// General unhandled I/O error.
     Io(io::Error),
 }
@@ -89,12 +215,77 @@ impl fmt::Display for Error {
             ConfigFileNotFound { path, .. } => {
                 write!(f, "config file not found: {}", path.display())?;
             }
+            UnsupportedConfigVersion { version } => {
+                write!(
+                    f,
+                    "unsupported config version: {} (supported: {})",
+                    version,
+                    crate::config::SUPPORTED_VERSION
+                )?;
+            }
+            ConfigFileReadFailed { path, io_err } => {
+                write!(f, "config file read error: {} {}", path.display(), io_err)?;
+            }
             ConfigFileParseFailed { path, yaml_err, .. } => write!(
                 f,
                 "config file parse error: {} {}",
                 path.display(),
                 yaml_err
             )?,
+            InvalidInclude { pattern, reason } => {
+                write!(f, "invalid include {}: {}", pattern, reason)?;
+            }
+            ConfigMergeConflict { path, key } => {
+                write!(
+                    f,
+                    "{} of {} conflicts with other config file",
+                    key,
+                    path.display()
+                )?;
+            }
+            DuplicateEntry { entry, files } => {
+                let files = files
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "duplicate entry {}: defined in {}",
+                    entry,
+                    files.join(" and ")
+                )?;
+            }
+            InvalidConfigDir { path, io_err } => {
+                write!(f, "invalid config directory: {} {}", path.display(), io_err)?;
+            }
+            EntryFieldMissing { entry, field } => {
+                write!(f, "{}: {} is required", entry, field)?;
+            }
+            EnvUndefined { entry, env } => {
+                write!(f, "{}: env {} is not defined", entry, env)?;
+            }
+            VarUndefined { var, path } => {
+                write!(f, "undefined variable: ${{{}}} in {}", var, path.display())?;
+            }
+            VarCycle { vars, path } => {
+                write!(
+                    f,
+                    "variable cycle: {} in {}",
+                    vars.join(" -> "),
+                    path.display()
+                )?;
+            }
+            InvalidInterpolation { reason, path } => {
+                write!(f, "invalid interpolation: {} in {}", reason, path.display())?;
+            }
+            ProfileNotFound { name, defined } => {
+                write!(
+                    f,
+                    "profile {} not found. defined profiles: {}",
+                    name,
+                    defined.join(", ")
+                )?;
+            }
             InvalidFilePermission { raw, .. } => {
                 write!(
                     f,
@@ -102,6 +293,52 @@ impl fmt::Display for Error {
                     raw
                 )?;
             }
+            InvalidCommandEntry { id, reason } => {
+                write!(f, "invalid command entry {}: {}", id, reason)?;
+            }
+            InvalidVersionRequirement { package, raw } => {
+                write!(
+                    f,
+                    "invalid version of {}: {} (expected e.g. `0.18.0`, `^0.18`, `>=0.17, <0.19`)",
+                    package, raw
+                )?;
+            }
+            RequiredEnvUndefined { envs } => {
+                write!(f, "required envs undefined:")?;
+                for (name, description) in envs {
+                    write!(f, "\n    {}: {}", name, description)?;
+                }
+            }
+            UndeclaredEnv { entry, env } => {
+                write!(
+                    f,
+                    "{} references env {} not declared in required_envs",
+                    entry, env
+                )?;
+            }
+            UnknownDependency { entry, dependency } => {
+                write!(f, "{} depends on undefined entry {}", entry, dependency)?;
+            }
+            DependencyCycle { entries } => {
+                write!(f, "dependency cycle: {}", entries.join(" -> "))?;
+            }
+            DependencyFailed { dependency } => {
+                write!(f, "skipped because {} failed", dependency)?;
+            }
+            TemplateRenderFailed {
+                path,
+                line,
+                message,
+            } => match line {
+                Some(line) => write!(
+                    f,
+                    "template render error: {}:{} {}",
+                    path.display(),
+                    line,
+                    message
+                )?,
+                None => write!(f, "template render error: {} {}", path.display(), message)?,
+            },
             CommandNotFound {
                 which_err, name, ..
             } => {
@@ -113,6 +350,42 @@ impl fmt::Display for Error {
                     which_err
                 )?
             }
+            CommandFailed { name, code } => {
+                use std::os::unix::ffi::OsStrExt;
+                let name = String::from_utf8_lossy(name.as_bytes());
+                match code {
+                    Some(code) => write!(f, "command {} failed: exit code {}", name, code)?,
+                    None => write!(f, "command {} terminated by signal", name)?,
+                }
+            }
+            CommandTimeout { name, timeout } => {
+                use std::os::unix::ffi::OsStrExt;
+                write!(
+                    f,
+                    "command {} timed out after {}s",
+                    String::from_utf8_lossy(name.as_bytes()),
+                    timeout.as_secs()
+                )?
+            }
+            StateDirUndefined => {
+                write!(
+                    f,
+                    "state directory undefined: set LOCALENV_STATE_DIR, XDG_STATE_HOME or HOME"
+                )?;
+            }
+            StateFileParseFailed { path, yaml_err } => {
+                write!(f, "state file parse error: {} {}", path.display(), yaml_err)?
+            }
+            UnsupportedStateVersion { version, path } => write!(
+                f,
+                "unsupported state file version: {} in {} (supported: {})",
+                version,
+                path.display(),
+                crate::state::STATE_VERSION
+            )?,
+            BackupNotFound { id } => {
+                write!(f, "backup not found: {}", id)?;
+            }
             Io(err) => {
                 write!(f, "I/O error: {}", err)?;
             }
@@ -5,6 +5,7 @@ mod config;
 mod error;
 mod operation;
 mod prelude;
+mod state;
 mod system;
 
 // Parse command line args, then dispatch process.
@@ -16,6 +17,7 @@ async fn run() {
     match cmd.subcommand {
         cli::SubCommand::Apply(opt) => cli::run_apply(opt).await,
         cli::SubCommand::Plan(opt) => cli::run_plan(opt).await,
+        cli::SubCommand::Restore(opt) => cli::run_restore(opt).await,
     }
 }
 
@@ -1,17 +1,36 @@
-use std::fs;
+use std::cell::RefCell;
+
+use futures::{
+    lock::Mutex,
+    stream::{FuturesUnordered, StreamExt},
+};
 
 use crate::{
-    config::{Config, FileEntry, SymlinkEntry},
-    operation::{CommandOperation, FilesystemOperation, OperationChain, OperationKind},
+    config::{Command, CommandEntry, Config, DirectoryEntry, FileEntry, SymlinkEntry},
+    operation::{
+        installer, template, Change, CommandOperation, FilesystemOperation, OperationChain,
+        OperationKind, SkipReason,
+    },
     prelude::*,
-    system,
+    state::{self, BackupStore, ManagedEntry, ManagedKind, ManagedPackage, State},
+    system::{self, CommandOutput, FileType},
 };
 
+use std::{collections::HashSet, path::Path};
+
 pub struct ApplyParam<'cfg, 'ops, Api> {
     pub system: Api,
     pub config: &'cfg Config,
     pub operation_chain: &'ops mut OperationChain,
+    /// Existing entries are saved here before being overwritten.
+    pub backup_store: &'ops mut BackupStore,
+    /// Updated with applied operations unless dry run.
+    pub state: &'ops mut State,
     pub dry_run: bool,
+    /// Run removal of entries dropped from config. Skipped otherwise.
+    pub prune: bool,
+    /// Maximum number of command operations run concurrently.
+    pub jobs: usize,
 }
 
 /// apply operations to system.
@@ -23,65 +42,633 @@ where
         mut system,
         config,
         operation_chain,
+        backup_store,
+        state,
         dry_run,
+        prune,
+        jobs,
     } = param;
 
-    for ops in operation_chain.operations_mut() {
-        let result = match ops.kind() {
-            OperationKind::Filesystem(ops_fs) => match ops_fs {
-                FilesystemOperation::CreateFile { entry, .. } => {
-                    apply_create_file_blocking(&mut system, config, dry_run, entry)
+    let descriptions = operation_chain
+        .operations()
+        .iter()
+        .map(|ops| ops.kind().description())
+        .collect::<Vec<_>>();
+    // Some(true) once operation succeeded, Some(false) once it failed or was skipped.
+    let mut done = vec![None; descriptions.len()];
+    loop {
+        // filesystem operations run in planned order while no command is running.
+        // dependencies come first in chain, so one pass settles every ready operation.
+        let mut progressed = false;
+        for (index, ops) in operation_chain.operations_mut().iter_mut().enumerate() {
+            if done[index].is_some() || ops.dependencies().iter().any(|d| done[*d].is_none()) {
+                continue;
+            }
+            if let Some(failed) = ops.dependencies().iter().find(|d| done[**d] == Some(false)) {
+                debug!(
+                    "skip {} depending on {}",
+                    descriptions[index], descriptions[*failed]
+                );
+                ops.set_result(Err(Error::from(ErrorKind::DependencyFailed {
+                    dependency: descriptions[*failed].clone(),
+                })));
+                done[index] = Some(false);
+                progressed = true;
+                continue;
+            }
+            if ops.kind().is_removal() && !prune {
+                debug!("skip removal without prune {:?}", ops.kind());
+                ops.set_skipped(SkipReason::PruneDisabled);
+                done[index] = Some(true);
+                progressed = true;
+                continue;
+            }
+
+            let ops_fs = match ops.kind() {
+                OperationKind::Filesystem(ops_fs) => ops_fs,
+                OperationKind::Command(_) => continue,
+            };
+            let result =
+                apply_filesystem_operation(&mut system, backup_store, config, dry_run, ops_fs);
+            let result = match result {
+                Ok(_) if !dry_run => record_state(&system, state, config, ops.kind()),
+                result => result,
+            };
+            done[index] = Some(result.is_ok());
+            ops.set_result(result);
+            progressed = true;
+        }
+
+        let outcomes = apply_commands(&system, config, dry_run, jobs, operation_chain, &done).await;
+        if outcomes.is_empty() && !progressed {
+            break;
+        }
+        let operations = operation_chain.operations_mut();
+        for (index, result, output) in outcomes {
+            let ops = &mut operations[index];
+            let result = match result {
+                Ok(_) if !dry_run => record_state(&system, state, config, ops.kind()),
+                result => result,
+            };
+            done[index] = Some(result.is_ok());
+            ops.set_result(result);
+            if let Some(output) = output {
+                ops.set_output(output);
+            }
+        }
+    }
+
+    if !dry_run {
+        update_state(&mut system, state, config, operation_chain).await?;
+    }
+
+    Ok(())
+}
+
+/// Apply filesystem operation. Overwritten entries are saved to backup store.
+fn apply_filesystem_operation<Api>(
+    system: &mut Api,
+    backup_store: &mut BackupStore,
+    config: &Config,
+    dry_run: bool,
+    ops_fs: &FilesystemOperation,
+) -> Result<()>
+where
+    Api: system::Api,
+{
+    match ops_fs {
+        FilesystemOperation::CreateFile { entry, change, .. } => {
+            apply_create_file_blocking(system, backup_store, config, dry_run, entry, *change)
+        }
+        FilesystemOperation::CreateSymbolicLink { entry, change } => {
+            apply_create_symbolic_link_blocking(
+                system,
+                backup_store,
+                config,
+                dry_run,
+                entry,
+                *change,
+            )
+        }
+        FilesystemOperation::CreateDirectory { entry, .. } => {
+            apply_create_directory_blocking(system, dry_run, entry)
+        }
+        FilesystemOperation::CreateIntermediateDirectory { path, permission } => {
+            if dry_run {
+                Ok(())
+            } else {
+                system.create_dir(path, *permission, false)
+            }
+        }
+        FilesystemOperation::RemoveFile { path }
+        | FilesystemOperation::RemoveSymbolicLink { path } => {
+            if dry_run {
+                Ok(())
+            } else {
+                backup_store
+                    .save(system, path)
+                    .and_then(|_| system.remove_file(path))
+            }
+        }
+    }
+}
+
+/// Run command operations whose dependencies succeeded, at most `jobs` at a time.
+/// Commands depending on other commands start as soon as those succeed.
+/// Outcomes are returned in planned order so that state is recorded deterministically.
+/// Output is streamed when run one by one, otherwise captured per operation.
+async fn apply_commands<Api>(
+    system: &Api,
+    cfg: &Config,
+    dry_run: bool,
+    jobs: usize,
+    operation_chain: &OperationChain,
+    done: &[Option<bool>],
+) -> Vec<(usize, Result<()>, Option<String>)>
+where
+    Api: system::Api,
+{
+    let jobs = jobs.max(1);
+    let operations = operation_chain.operations();
+    let mut done = done.to_vec();
+    let mut started = vec![false; operations.len()];
+    // brew holds a global lock while installing, so formulae are installed one by one.
+    let brew_lock = Mutex::new(());
+
+    let run = |index: usize| {
+        let brew_lock = &brew_lock;
+        async move {
+            let capture = OutputCapture::new(system, jobs == 1);
+            let result = match operations[index].kind() {
+                OperationKind::Command(ops_cmd) => match ops_cmd {
+                    CommandOperation::Install { cmd }
+                    | CommandOperation::Upgrade { cmd, .. }
+                    | CommandOperation::Downgrade { cmd, .. } => {
+                        let _guard = match cmd {
+                            Command::Brew(_) => Some(brew_lock.lock().await),
+                            _ => None,
+                        };
+                        apply_install_command(&capture, cfg, dry_run, cmd, false).await
+                    }
+                    CommandOperation::Reinstall { cmd, .. } => {
+                        apply_install_command(&capture, cfg, dry_run, cmd, true).await
+                    }
+                    CommandOperation::Uninstall { package } => {
+                        apply_uninstall_command(&capture, dry_run, package).await
+                    }
+                },
+                OperationKind::Filesystem(_) => {
+                    Error::internal("filesystem operation is not a command")
                 }
-                FilesystemOperation::CreateSymbolicLink { entry, .. } => {
-                    apply_create_symbolic_link_blocking(&mut system, config, dry_run, entry)
+            };
+            (index, result, capture.into_output())
+        }
+    };
+
+    let mut running = FuturesUnordered::new();
+    let mut outcomes = Vec::new();
+    loop {
+        while running.len() < jobs {
+            let next = operations.iter().enumerate().position(|(index, ops)| {
+                !started[index]
+                    && done[index].is_none()
+                    && matches!(ops.kind(), OperationKind::Command(_))
+                    && ops.dependencies().iter().all(|d| done[*d] == Some(true))
+            });
+            match next {
+                Some(index) => {
+                    started[index] = true;
+                    running.push(run(index));
                 }
+                None => break,
+            }
+        }
+
+        match running.next().await {
+            Some((index, result, output)) => {
+                done[index] = Some(result.is_ok());
+                outcomes.push((index, result, output));
+            }
+            None => break,
+        }
+    }
+
+    outcomes.sort_by_key(|(index, ..)| *index);
+    outcomes
+}
+
+/// Run commands through inner api, capturing output of commands which would stream it
+/// so that outputs of concurrent operations are not interleaved.
+struct OutputCapture<'a, Cmd> {
+    inner: &'a Cmd,
+    /// Keep streaming instead of capturing.
+    stream: bool,
+    output: RefCell<Option<String>>,
+}
+
+impl<'a, Cmd> OutputCapture<'a, Cmd> {
+    fn new(inner: &'a Cmd, stream: bool) -> Self {
+        Self {
+            inner,
+            stream,
+            output: RefCell::new(None),
+        }
+    }
+
+    fn into_output(self) -> Option<String> {
+        self.output.into_inner()
+    }
+}
+
+impl<Cmd> system::CommandApi for OutputCapture<'_, Cmd>
+where
+    Cmd: system::CommandApi,
+{
+    async fn run_command(&self, cmd: &system::Command) -> Result<CommandOutput> {
+        if self.stream || !cmd.is_stream() {
+            return self.inner.run_command(cmd).await;
+        }
+
+        let output = self.inner.run_command(&cmd.clone().stream(false)).await?;
+        self.output
+            .borrow_mut()
+            .get_or_insert_with(String::new)
+            .extend(
+                [&output.stdout, &output.stderr]
+                    .iter()
+                    .map(|out| String::from_utf8_lossy(out)),
+            );
+
+        Ok(output)
+    }
+}
+
+/// Record applied operation to state.
+fn record_state<Api>(
+    system: &Api,
+    state: &mut State,
+    cfg: &Config,
+    kind: &OperationKind,
+) -> Result<()>
+where
+    Api: system::Api,
+{
+    match kind {
+        OperationKind::Filesystem(ops_fs) => match ops_fs {
+            FilesystemOperation::CreateFile { entry, change, .. } => {
+                let content = template::file_content(system, cfg, entry)?;
+                state.upsert_entry(ManagedEntry {
+                    path: entry.dest_path()?,
+                    kind: ManagedKind::File {
+                        content_hash: state::content_hash(&content),
+                        mode: entry.permission()?.to_string(),
+                    },
+                    created: *change == Change::Create,
+                });
+            }
+            FilesystemOperation::CreateSymbolicLink { entry, change } => {
+                state.upsert_entry(ManagedEntry {
+                    path: entry.link_path()?,
+                    kind: ManagedKind::SymbolicLink {
+                        original: entry.original_path()?,
+                    },
+                    created: *change == Change::Create,
+                });
+            }
+            FilesystemOperation::CreateDirectory { entry, change } => {
+                state.upsert_entry(ManagedEntry {
+                    path: entry.path()?,
+                    kind: ManagedKind::Directory {
+                        mode: entry.permission()?.to_string(),
+                    },
+                    created: *change == Change::Create,
+                });
+            }
+            FilesystemOperation::CreateIntermediateDirectory { .. } => (),
+            FilesystemOperation::RemoveFile { path }
+            | FilesystemOperation::RemoveSymbolicLink { path } => state.remove_entry(path),
+        },
+        OperationKind::Command(ops_cmd) => match ops_cmd {
+            CommandOperation::Install { cmd } => match cmd {
+                Command::Cargo(cargo_cmd) => state.upsert_package(ManagedPackage {
+                    installer: installer::CARGO.to_owned(),
+                    package: cargo_cmd.package().to_owned(),
+                    version: None,
+                    root: cargo_cmd.root.clone(),
+                    created: true,
+                }),
+                Command::Brew(brew_cmd) => state.upsert_package(ManagedPackage {
+                    installer: installer::BREW.to_owned(),
+                    package: brew_cmd.package().to_owned(),
+                    version: None,
+                    root: None,
+                    created: true,
+                }),
+                Command::Go(go_cmd) => state.upsert_package(ManagedPackage {
+                    installer: installer::GO.to_owned(),
+                    package: go_cmd.package().to_owned(),
+                    version: None,
+                    root: None,
+                    created: true,
+                }),
             },
-            OperationKind::Command(ops_cmd) => match ops_cmd {
-                CommandOperation::Install { cmd: _, .. } => Error::internal("not implemented"),
-            },
-        };
+            CommandOperation::Reinstall { cmd, .. } => state.upsert_package(ManagedPackage {
+                installer: installer::name(cmd).to_owned(),
+                package: cmd.package().to_owned(),
+                version: None,
+                root: cmd.root().map(Path::to_path_buf),
+                created: false,
+            }),
+            CommandOperation::Upgrade {
+                cmd, requirement, ..
+            }
+            | CommandOperation::Downgrade {
+                cmd, requirement, ..
+            } => state.upsert_package(ManagedPackage {
+                installer: installer::name(cmd).to_owned(),
+                package: cmd.package().to_owned(),
+                version: requirement.exact().map(ToString::to_string),
+                root: cmd.root().map(Path::to_path_buf),
+                created: false,
+            }),
+            CommandOperation::Uninstall { package } => state.remove_package(
+                &package.installer,
+                &package.package,
+                package.root.as_deref(),
+            ),
+        },
+    }
 
-        ops.set_result(result);
+    Ok(())
+}
+
+/// Forget entries no longer managed and refresh installed package versions.
+/// Packages are refreshed only for commands planned, so installers of commands skipped by
+/// condition or profile are not run.
+async fn update_state<Api>(
+    system: &mut Api,
+    state: &mut State,
+    cfg: &Config,
+    chain: &OperationChain,
+) -> Result<()>
+where
+    Api: system::Api,
+{
+    state.config_revision = Some(cfg.revision.clone());
+
+    // entries created by localenv are kept until they are actually removed.
+    let listed = cfg
+        .spec
+        .filesystem
+        .entries
+        .iter()
+        .map(|entry| entry.path())
+        .collect::<Result<HashSet<_>>>()?;
+    let mut filesystem = Vec::new();
+    for entry in state.filesystem.drain(..) {
+        if listed.contains(&entry.path)
+            || (entry.created && system.file_type(&entry.path)?.is_some())
+        {
+            filesystem.push(entry);
+        }
+    }
+    state.filesystem = filesystem;
+
+    let commands = &cfg.spec.commands;
+    if let Some(cargo_commands) = &commands.cargo {
+        state.packages.retain(|p| {
+            p.installer != installer::CARGO
+                || p.created
+                || cargo_commands
+                    .iter()
+                    .any(|c| p.is(installer::CARGO, c.package(), c.root.as_deref()))
+        });
+    }
+
+    let cargo_commands = planned(commands.cargo.as_deref(), chain);
+    if !cargo_commands.is_empty() {
+        match installer::Cargo::new(&mut *system)
+            .list_installed_packages_by_root(cargo_commands.iter().map(|c| c.root.as_deref()))
+            .await
+        {
+            Ok(installed) => {
+                for cmd in cargo_commands {
+                    if let Some(p) = installed.find(cmd) {
+                        state.upsert_package(ManagedPackage {
+                            installer: installer::CARGO.to_owned(),
+                            package: cmd.package().to_owned(),
+                            version: Some(p.version().to_string()),
+                            root: cmd.root.clone(),
+                            created: false,
+                        });
+                    }
+                }
+            }
+            Err(err) => warn!("could not refresh installed cargo packages: {}", err),
+        }
+    }
+
+    if let Some(brew_commands) = &commands.brew {
+        state.packages.retain(|p| {
+            p.installer != installer::BREW
+                || p.created
+                || brew_commands.iter().any(|c| c.package() == p.package)
+        });
+    }
+
+    let brew_commands = planned(commands.brew.as_deref(), chain);
+    if !brew_commands.is_empty() {
+        match installer::Brew::new(&mut *system)
+            .list_installed_formulae()
+            .await
+        {
+            Ok(installed) => {
+                for cmd in brew_commands {
+                    if let Some(f) = installed.iter().find(|f| f.matches(cmd)) {
+                        state.upsert_package(ManagedPackage {
+                            installer: installer::BREW.to_owned(),
+                            package: cmd.package().to_owned(),
+                            version: f.version().map(str::to_owned),
+                            root: None,
+                            created: false,
+                        });
+                    }
+                }
+            }
+            Err(err) => warn!("could not refresh installed brew formulae: {}", err),
+        }
+    }
+
+    if let Some(go_commands) = &commands.go {
+        state.packages.retain(|p| {
+            p.installer != installer::GO
+                || p.created
+                || go_commands.iter().any(|c| c.package() == p.package)
+        });
+    }
+
+    let go_commands = planned(commands.go.as_deref(), chain);
+    if !go_commands.is_empty() {
+        let mut go = installer::Go::new(&mut *system, cfg.spec.lang.goroot());
+        match go.bin_dir().await {
+            Ok(bin_dir) => {
+                for cmd in go_commands {
+                    match go.installed_binary(&bin_dir, cmd).await {
+                        Ok(Some(binary)) => state.upsert_package(ManagedPackage {
+                            installer: installer::GO.to_owned(),
+                            package: cmd.package().to_owned(),
+                            version: binary.version().map(str::to_owned),
+                            root: None,
+                            created: false,
+                        }),
+                        Ok(None) => (),
+                        Err(err) => warn!("could not refresh go binary {}: {}", cmd.bin(), err),
+                    }
+                }
+            }
+            Err(err) => warn!("could not refresh installed go binaries: {}", err),
+        }
     }
 
     Ok(())
 }
 
+/// Commands of config which were planned, not skipped by condition, profile or unavailable installer.
+fn planned<'a, C: CommandEntry>(commands: Option<&'a [C]>, chain: &OperationChain) -> Vec<&'a C> {
+    commands
+        .unwrap_or_default()
+        .iter()
+        .filter(|c| !chain.is_skipped(c.base().id()))
+        .collect()
+}
+
 fn apply_create_file_blocking<Api>(
     system: &mut Api,
+    backup_store: &mut BackupStore,
     cfg: &Config,
     dry_run: bool,
     entry: &FileEntry,
+    change: Change,
 ) -> Result<()>
 where
     Api: system::Api,
 {
-    let dest = entry.dest_path();
-    let src = entry.src_path(cfg.root_dir.as_path());
-    let mut content = fs::File::open(src.as_path())?;
+    let dest = entry.dest_path()?;
+    let content = template::file_content(system, cfg, entry)?;
 
     if dry_run {
         Ok(())
     } else {
-        system.create_file(dest, &mut content, entry.permission()?)
+        if change == Change::Update {
+            backup_store.save(system, &dest)?;
+            // replace link itself instead of writing through it.
+            if system.file_type(&dest)? == Some(FileType::SymbolicLink) {
+                system.remove_file(&dest)?;
+            }
+        }
+        system.create_file(dest, content.as_slice(), entry.permission()?)
     }
 }
 
 fn apply_create_symbolic_link_blocking<Api>(
     system: &mut Api,
+    backup_store: &mut BackupStore,
     _cfg: &Config,
     dry_run: bool,
     entry: &SymlinkEntry,
+    change: Change,
 ) -> Result<()>
 where
     Api: system::Api,
 {
-    let original = entry.original_path();
-    let link = entry.link_path();
+    let original = entry.original_path()?;
+    let link = entry.link_path()?;
 
     if dry_run {
         Ok(())
     } else {
+        if change == Change::Update {
+            backup_store.save(system, &link)?;
+        }
         system.create_symbolic_link(original, link)
     }
 }
+
+fn apply_create_directory_blocking<Api>(
+    system: &mut Api,
+    dry_run: bool,
+    entry: &DirectoryEntry,
+) -> Result<()>
+where
+    Api: system::Api,
+{
+    let path = entry.path()?;
+    let permission = entry.permission()?;
+
+    if dry_run {
+        Ok(())
+    } else {
+        system.create_dir(path, permission, entry.recursive)
+    }
+}
+
+async fn apply_install_command<Cmd>(
+    system: &Cmd,
+    cfg: &Config,
+    dry_run: bool,
+    cmd: &Command,
+    force: bool,
+) -> Result<()>
+where
+    Cmd: system::CommandApi,
+{
+    match cmd {
+        Command::Cargo(cargo_cmd) => {
+            if dry_run {
+                Ok(())
+            } else {
+                installer::Cargo::new(system)
+                    .install(cargo_cmd, force)
+                    .await
+            }
+        }
+        Command::Brew(brew_cmd) => {
+            if dry_run {
+                Ok(())
+            } else {
+                installer::Brew::new(system).install(brew_cmd).await
+            }
+        }
+        Command::Go(go_cmd) => {
+            if dry_run {
+                Ok(())
+            } else {
+                installer::Go::new(system, cfg.spec.lang.goroot())
+                    .install(go_cmd)
+                    .await
+            }
+        }
+    }
+}
+
+async fn apply_uninstall_command<Cmd>(
+    system: &Cmd,
+    dry_run: bool,
+    package: &ManagedPackage,
+) -> Result<()>
+where
+    Cmd: system::CommandApi,
+{
+    match package.installer.as_str() {
+        installer::CARGO => {
+            if dry_run {
+                Ok(())
+            } else {
+                installer::Cargo::new(system)
+                    .uninstall(&package.package, package.root.as_deref())
+                    .await
+            }
+        }
+        _ => Error::internal("not implemented"),
+    }
+}
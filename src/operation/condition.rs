@@ -0,0 +1,181 @@
+use crate::{config::Condition, prelude::*, system};
+
+const OS_RELEASE: &str = "/etc/os-release";
+
+/// Describe first unmet part of condition. None if condition is satisfied.
+pub(super) fn unmet<Api>(sys: &Api, cond: &Condition) -> Result<Option<String>>
+where
+    Api: system::Api,
+{
+    if let Some(os) = cond.os {
+        if os != sys.os() {
+            return Ok(Some(format!("os is {}, not {}", sys.os(), os)));
+        }
+    }
+    if let Some(ref hostname) = cond.hostname {
+        let actual = sys.hostname()?;
+        if &actual != hostname {
+            return Ok(Some(format!("hostname is {}, not {}", actual, hostname)));
+        }
+    }
+    if let Some(ref username) = cond.username {
+        let actual = sys.username();
+        if &actual != username {
+            return Ok(Some(format!("username is {}, not {}", actual, username)));
+        }
+    }
+    // arch is the target localenv is built for, not the running CPU. see `Condition::arch`.
+    if let Some(ref arch) = cond.arch {
+        if arch != sys.arch() {
+            return Ok(Some(format!("arch is {}, not {}", sys.arch(), arch)));
+        }
+    }
+    if let Some(ref distro) = cond.distro {
+        match self::distro(sys)? {
+            Some(actual) if actual.eq_ignore_ascii_case(distro) => (),
+            Some(actual) => return Ok(Some(format!("distro is {}, not {}", actual, distro))),
+            None => return Ok(Some(format!("distro is unknown, not {}", distro))),
+        }
+    }
+    if let Some(ref cond_env) = cond.env {
+        match (sys.env_var(&cond_env.name), cond_env.value.as_ref()) {
+            (None, _) => return Ok(Some(format!("env {} is not defined", cond_env.name))),
+            (Some(actual), Some(value)) if &actual != value => {
+                return Ok(Some(format!(
+                    "env {} is {}, not {}",
+                    cond_env.name, actual, value
+                )))
+            }
+            _ => (),
+        }
+    }
+    if let Some(ref path) = cond.path_exists {
+        if sys.file_type(path)?.is_none() {
+            return Ok(Some(format!("path {} does not exist", path.display())));
+        }
+    }
+    if let Some(ref binary) = cond.binary {
+        if sys.binary_path(binary).is_none() {
+            return Ok(Some(format!("binary {} not found in $PATH", binary)));
+        }
+    }
+    for cond in cond.all.iter() {
+        if let Some(reason) = unmet(sys, cond)? {
+            return Ok(Some(reason));
+        }
+    }
+    if !cond.any.is_empty() {
+        let mut reasons = Vec::new();
+        for cond in cond.any.iter() {
+            match unmet(sys, cond)? {
+                Some(reason) => reasons.push(reason),
+                None => {
+                    reasons.clear();
+                    break;
+                }
+            }
+        }
+        if !reasons.is_empty() {
+            return Ok(Some(format!("none of any matched: {}", reasons.join("; "))));
+        }
+    }
+    if let Some(ref not) = cond.not {
+        if unmet(sys, not)?.is_none() {
+            return Ok(Some(format!("{} (negated by not)", not)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// `ID` in os-release. None if os-release does not exist.
+fn distro<Api>(sys: &Api) -> Result<Option<String>>
+where
+    Api: system::Api,
+{
+    if sys.file_type(OS_RELEASE)?.is_none() {
+        return Ok(None);
+    }
+    let content = sys.read_file(OS_RELEASE)?;
+    Ok(String::from_utf8_lossy(&content).lines().find_map(|line| {
+        line.strip_prefix("ID=")
+            .map(|id| id.trim().trim_matches(|c| c == '"' || c == '\'').to_owned())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{fake::FakeSystem, FilePermission, Os};
+
+    fn unmet_yaml(sys: &FakeSystem, yaml: &str) -> Option<String> {
+        unmet(sys, &serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn evaluate_condition() {
+        let sys = FakeSystem::new()
+            .with_os(Os::Linux)
+            .with_hostname("devbox")
+            .with_username("alice")
+            .with_arch("aarch64")
+            .with_env("HOME", "/home/alice")
+            .with_env("PATH", "/usr/local/bin:/bin")
+            .with_file("/bin/sh", "", FilePermission::UnixMode(0o755))
+            .with_directory("/etc", FilePermission::UnixMode(0o755))
+            .with_file(
+                OS_RELEASE,
+                "NAME=\"Ubuntu\"\nID=ubuntu\nID_LIKE=debian\n",
+                FilePermission::UnixMode(0o644),
+            );
+
+        assert_eq!(
+            unmet_yaml(
+                &sys,
+                "{ os: linux, hostname: devbox, username: alice, distro: Ubuntu, path_exists: /etc, binary: sh, env: { name: HOME } }"
+            ),
+            None
+        );
+        assert_eq!(unmet_yaml(&sys, "arch: aarch64"), None);
+        assert_eq!(
+            unmet_yaml(&sys, "arch: x86_64"),
+            Some("arch is aarch64, not x86_64".to_owned())
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "hostname: work"),
+            Some("hostname is devbox, not work".to_owned())
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "distro: arch"),
+            Some("distro is ubuntu, not arch".to_owned())
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "binary: localenv-no-such-binary"),
+            Some("binary localenv-no-such-binary not found in $PATH".to_owned())
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "env: { name: LOCALENV_TEST_UNDEFINED }"),
+            Some("env LOCALENV_TEST_UNDEFINED is not defined".to_owned())
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "env: { name: HOME, value: /root }"),
+            Some("env HOME is /home/alice, not /root".to_owned())
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "any: [{ os: mac }, { username: alice }]"),
+            None
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "any: [{ os: mac }, { path_exists: /opt }]"),
+            Some("none of any matched: os is linux, not mac; path /opt does not exist".to_owned())
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "all: [{ os: linux }, { not: { username: alice } }]"),
+            Some("username is alice (negated by not)".to_owned())
+        );
+        assert_eq!(
+            unmet_yaml(&sys, "not: { any: [{ os: linux }, { hostname: work }] }"),
+            Some("any of (os is linux; hostname is work) (negated by not)".to_owned())
+        );
+    }
+}
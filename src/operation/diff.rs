@@ -0,0 +1,258 @@
+use colored::*;
+use similar::TextDiff;
+
+use std::{fmt::Write, path::Path};
+
+use crate::system::FilePermission;
+
+/// Files larger than this are not diffed.
+const MAX_DIFF_INPUT_BYTES: usize = 1024 * 1024;
+/// Diff output is truncated after this many lines.
+const MAX_DIFF_LINES: usize = 200;
+/// Number of bytes inspected to detect binary content.
+const BINARY_DETECTION_BYTES: usize = 8000;
+
+/// Difference between current and desired file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    /// (current, desired) if mode changes.
+    pub permission: Option<(FilePermission, FilePermission)>,
+    pub content: ContentDiff,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentDiff {
+    Same,
+    /// Unified diff limited to MAX_DIFF_LINES.
+    Text {
+        unified: String,
+        truncated_lines: usize,
+    },
+    Binary,
+    TooLarge,
+}
+
+impl FileDiff {
+    pub fn new(
+        current_path: &Path,
+        current: &[u8],
+        current_permission: FilePermission,
+        desired_path: &Path,
+        desired: &[u8],
+        desired_permission: FilePermission,
+    ) -> Self {
+        let permission = if current_permission != desired_permission {
+            Some((current_permission, desired_permission))
+        } else {
+            None
+        };
+
+        Self {
+            permission,
+            content: ContentDiff::new(current_path, current, desired_path, desired),
+        }
+    }
+
+    /// Render colored diff indented for plan output.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        if let Some((current, desired)) = self.permission {
+            let _ = writeln!(out, "    Mode: {} -> {}", current, desired);
+        }
+
+        match &self.content {
+            ContentDiff::Same => (),
+            ContentDiff::Binary => {
+                let _ = writeln!(out, "    Binary files differ");
+            }
+            ContentDiff::TooLarge => {
+                let _ = writeln!(
+                    out,
+                    "    Files differ (larger than {} bytes, diff omitted)",
+                    MAX_DIFF_INPUT_BYTES
+                );
+            }
+            ContentDiff::Text {
+                unified,
+                truncated_lines,
+            } => {
+                for line in unified.lines() {
+                    let line = if line.starts_with("+++") || line.starts_with("---") {
+                        line.bold()
+                    } else if line.starts_with('+') {
+                        line.green()
+                    } else if line.starts_with('-') {
+                        line.red()
+                    } else if line.starts_with("@@") {
+                        line.cyan()
+                    } else {
+                        line.normal()
+                    };
+                    let _ = writeln!(out, "    {}", line);
+                }
+                if *truncated_lines > 0 {
+                    let _ = writeln!(out, "    ... {} more lines", truncated_lines);
+                }
+            }
+        }
+
+        out.trim_end().to_owned()
+    }
+}
+
+impl ContentDiff {
+    fn new(current_path: &Path, current: &[u8], desired_path: &Path, desired: &[u8]) -> Self {
+        if current == desired {
+            return ContentDiff::Same;
+        }
+        if current.len() > MAX_DIFF_INPUT_BYTES || desired.len() > MAX_DIFF_INPUT_BYTES {
+            return ContentDiff::TooLarge;
+        }
+
+        let (current, desired) = match (text(current), text(desired)) {
+            (Some(current), Some(desired)) => (current, desired),
+            _ => return ContentDiff::Binary,
+        };
+
+        let unified = TextDiff::from_lines(current, desired)
+            .unified_diff()
+            .context_radius(3)
+            .header(
+                &current_path.display().to_string(),
+                &desired_path.display().to_string(),
+            )
+            .to_string();
+
+        let total = unified.lines().count();
+        let truncated_lines = total.saturating_sub(MAX_DIFF_LINES);
+        let unified = if truncated_lines > 0 {
+            unified
+                .lines()
+                .take(MAX_DIFF_LINES)
+                .fold(String::new(), |mut acc, line| {
+                    acc.push_str(line);
+                    acc.push('\n');
+                    acc
+                })
+        } else {
+            unified
+        };
+
+        ContentDiff::Text {
+            unified,
+            truncated_lines,
+        }
+    }
+}
+
+/// Interpret content as text unless it looks binary.
+fn text(content: &[u8]) -> Option<&str> {
+    let head = &content[..content.len().min(BINARY_DETECTION_BYTES)];
+    if head.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const MODE_644: FilePermission = FilePermission::UnixMode(0o644);
+
+    /// Remove ANSI color sequences so assertions hold whether or not color is enabled.
+    fn uncolored(s: &str) -> String {
+        let mut out = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| *c == 'm');
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn text_diff() {
+        let diff = FileDiff::new(
+            Path::new("/home/.tmux.conf"),
+            b"a\nb\nc\n",
+            FilePermission::UnixMode(0o600),
+            Path::new("static/tmux.conf"),
+            b"a\nB\nc\n",
+            MODE_644,
+        );
+
+        assert_eq!(
+            diff.permission,
+            Some((FilePermission::UnixMode(0o600), MODE_644))
+        );
+        assert_eq!(
+            diff.content,
+            ContentDiff::Text {
+                unified:
+                    "--- /home/.tmux.conf\n+++ static/tmux.conf\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+                        .to_owned(),
+                truncated_lines: 0,
+            }
+        );
+
+        assert!(uncolored(&diff.render()).starts_with("    Mode: 0600 -> 0644\n"));
+    }
+
+    #[test]
+    fn truncate_long_diff() {
+        let current = (0..500).map(|i| format!("{}\n", i)).collect::<String>();
+        let diff = FileDiff::new(
+            Path::new("f"),
+            b"",
+            MODE_644,
+            Path::new("g"),
+            current.as_bytes(),
+            MODE_644,
+        );
+
+        match diff.content {
+            ContentDiff::Text {
+                unified,
+                truncated_lines,
+            } => {
+                assert_eq!(unified.lines().count(), MAX_DIFF_LINES);
+                assert_eq!(truncated_lines, 503 - MAX_DIFF_LINES);
+            }
+            content => panic!("unexpected {:?}", content),
+        }
+    }
+
+    #[test]
+    fn binary_and_large_content() {
+        assert_eq!(
+            FileDiff::new(
+                Path::new("f"),
+                b"a\0",
+                MODE_644,
+                Path::new("g"),
+                b"b\0",
+                MODE_644
+            )
+            .content,
+            ContentDiff::Binary
+        );
+        assert_eq!(
+            FileDiff::new(
+                Path::new("f"),
+                b"",
+                MODE_644,
+                Path::new("g"),
+                &vec![b'a'; MAX_DIFF_INPUT_BYTES + 1],
+                MODE_644
+            )
+            .content,
+            ContentDiff::TooLarge
+        );
+    }
+}
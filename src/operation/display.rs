@@ -19,35 +19,76 @@ where
 {
     let DisplayParam {
         system,
-        config: _config,
+        config,
         operation_chain,
     } = param;
 
+    if let Some(profile) = config.profile.as_deref() {
+        system.display(format!("[Profile] {}", profile).bold());
+    }
+
     for ops in operation_chain.operations() {
         match ops.kind() {
             OperationKind::Filesystem(fs) => match fs {
-                FilesystemOperation::CreateFile { entry, .. } => {
-                    let dest = entry.dest_path();
+                FilesystemOperation::CreateFile {
+                    entry,
+                    change,
+                    diff,
+                } => {
+                    let dest = entry.dest_path()?;
 
                     let msg = format!(
-                        "[Create file]\n    Desc: {}\n    File: {}",
+                        "[{} file]\n    Desc: {}\n    File: {}",
+                        change.label(),
                         entry.description(),
                         dest.display(),
                     );
 
                     system.display(msg.yellow());
+                    if let Some(diff) = diff {
+                        system.display(diff.render());
+                    }
                 }
-                FilesystemOperation::CreateSymbolicLink { entry, .. } => {
-                    let original = entry.original_path();
-                    let link = entry.link_path();
+                FilesystemOperation::CreateSymbolicLink { entry, change } => {
+                    let original = entry.original_path()?;
+                    let link = entry.link_path()?;
 
                     let msg = format!(
-                        "[Create symlink]\n    Desc: {}\n    Orig: {}\n    Link: {}",
+                        "[{} symlink]\n    Desc: {}\n    Orig: {}\n    Link: {}",
+                        change.label(),
                         entry.description(),
                         original.display(),
                         link.display(),
                     );
 
+                    system.display(msg.yellow());
+                }
+                FilesystemOperation::CreateDirectory { entry, change } => {
+                    let msg = format!(
+                        "[{} directory]\n    Desc: {}\n     Dir: {}",
+                        change.label(),
+                        entry.description(),
+                        entry.path()?.display(),
+                    );
+
+                    system.display(msg.yellow());
+                }
+                FilesystemOperation::RemoveFile { path } => {
+                    let msg = format!("[Remove file]\n    File: {}", path.display());
+
+                    system.display(msg.red());
+                }
+                FilesystemOperation::RemoveSymbolicLink { path } => {
+                    let msg = format!("[Remove symlink]\n    Link: {}", path.display());
+
+                    system.display(msg.red());
+                }
+                FilesystemOperation::CreateIntermediateDirectory { path, .. } => {
+                    let msg = format!(
+                        "[Create directory]\n    Desc: intermediate directory\n     Dir: {}",
+                        path.display(),
+                    );
+
                     system.display(msg.yellow());
                 }
             },
@@ -57,6 +98,56 @@ where
 
                     system.display(msg.yellow());
                 }
+                CommandOperation::Reinstall {
+                    cmd,
+                    installed,
+                    configured,
+                } => {
+                    let msg = format!(
+                        "[Reinstall command]\n Package: {}\n  Source: {} -> {}",
+                        cmd.package(),
+                        installed,
+                        configured
+                    );
+
+                    system.display(msg.yellow());
+                }
+                CommandOperation::Upgrade {
+                    cmd,
+                    installed,
+                    requirement,
+                } => {
+                    let msg = format!(
+                        "[Upgrade command]\n Package: {} {} -> {}",
+                        cmd.package(),
+                        installed,
+                        requirement
+                    );
+
+                    system.display(msg.yellow());
+                }
+                CommandOperation::Downgrade {
+                    cmd,
+                    installed,
+                    requirement,
+                } => {
+                    let msg = format!(
+                        "[Downgrade command]\n Package: {} {} -> {}",
+                        cmd.package(),
+                        installed,
+                        requirement
+                    );
+
+                    system.display(msg.yellow());
+                }
+                CommandOperation::Uninstall { package } => {
+                    let msg = format!(
+                        "[Uninstall command]\n Package: {}\n    With: {}",
+                        package.package, package.installer
+                    );
+
+                    system.display(msg.red());
+                }
             },
         }
 
@@ -69,6 +160,21 @@ where
             let msg = format!("  Result: {}", result);
             system.display(msg.yellow());
         }
+        if let Some(reason) = ops.skipped() {
+            let msg = format!("  Result: Skipped, {}", reason);
+            system.display(msg.dimmed());
+        }
+        if let Some(output) = ops.output().filter(|o| !o.trim().is_empty()) {
+            system.display("  Output:".yellow());
+            for line in output.lines() {
+                system.display(format_args!("    {}", line));
+            }
+        }
+    }
+
+    for (entry, reason) in operation_chain.skipped() {
+        let msg = format!("[Skip]\n    Desc: {}\n  Reason: {}", entry, reason);
+        system.display(msg.dimmed());
     }
 
     Ok(())
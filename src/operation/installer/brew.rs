@@ -0,0 +1,271 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    config::BrewCommand,
+    prelude::*,
+    system::{self, Command},
+};
+
+const BREW_BIN: &str = "brew";
+
+/// Installer name recorded in state.
+pub const BREW: &str = "brew";
+
+/// `brew list` only reads local Cellar and Caskroom, so it should return quickly.
+const LIST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// homebrew formula or cask.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Formula {
+    name: String,
+    /// Installed versions in listed order.
+    versions: Vec<String>,
+    cask: bool,
+}
+
+impl Formula {
+    /// Last listed version.
+    pub fn version(&self) -> Option<&str> {
+        self.versions.last().map(String::as_str)
+    }
+
+    /// Whether formula matches given command. Tap prefix of command package is ignored.
+    pub fn matches(&self, cmd: &BrewCommand) -> bool {
+        self.cask == cmd.cask && self.name == cmd.formula_name()
+    }
+
+    fn with_cask(mut self, cask: bool) -> Self {
+        self.cask = cask;
+        self
+    }
+}
+
+pub struct Brew<Sys> {
+    sys: Sys,
+    bin: PathBuf,
+}
+
+impl<Sys> Brew<Sys>
+where
+    Sys: system::CommandApi,
+{
+    pub fn new(sys: Sys) -> Self {
+        Self {
+            sys,
+            bin: PathBuf::from(BREW_BIN),
+        }
+    }
+
+    /// Use given brew executable instead of one found in PATH.
+    #[cfg(test)]
+    fn with_bin(mut self, bin: impl Into<PathBuf>) -> Self {
+        self.bin = bin.into();
+        self
+    }
+
+    /// List installed formulae followed by installed casks.
+    pub async fn list_installed_formulae(&mut self) -> Result<Vec<Formula>> {
+        let mut formulae = self.list("--formula").await?;
+        formulae.extend(
+            self.list("--cask")
+                .await?
+                .into_iter()
+                .map(|f| f.with_cask(true)),
+        );
+
+        Ok(formulae)
+    }
+
+    async fn list(&mut self, kind: &str) -> Result<Vec<Formula>> {
+        let list = Command::new(&self.bin)
+            .args(["list", kind, "--versions"])
+            .timeout(LIST_TIMEOUT);
+        let output = self.sys.run_command(&list).await?.ensure_success(&list)?;
+
+        let output = String::from_utf8_lossy(output.stdout.as_slice());
+
+        parse_list(output)
+    }
+
+    /// Run `brew install` for given command, streaming its output.
+    pub async fn install(&mut self, cmd: &BrewCommand) -> Result<()> {
+        info!("brew install {}", cmd.package());
+
+        let mut install = Command::new(&self.bin).arg("install");
+        if cmd.cask {
+            install = install.arg("--cask");
+        }
+        let install = install.arg(cmd.package()).stream(true);
+
+        self.sys
+            .run_command(&install)
+            .await?
+            .ensure_success(&install)
+            .map(|_| ())
+    }
+}
+
+fn parse_list(s: impl AsRef<str>) -> Result<Vec<Formula>> {
+    // nothing installed yet.
+    if s.as_ref().trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    match parser::formula_list(s.as_ref()) {
+        Ok((_, formulae)) => Ok(formulae),
+        Err(nom_err) => Err(Error::from(ErrorKind::Internal(format!(
+            "failed to parse installed formula list: {:?}",
+            nom_err
+        )))),
+    }
+}
+
+/// brew command output parser module.
+mod parser {
+    use super::Formula;
+    use nom::bytes::complete;
+    use nom::character;
+    use nom::combinator;
+    use nom::multi;
+    use nom::sequence;
+    use nom::IResult;
+
+    /// parse whitespace separated token. formula names may contain `@`, `+` and `.`.
+    fn token(i: &str) -> IResult<&str, &str> {
+        complete::take_while1(|c: char| !c.is_whitespace())(i)
+    }
+
+    /// consume spaces within a line.
+    fn space(i: &str) -> IResult<&str, ()> {
+        combinator::map(character::complete::space1, |_| ())(i)
+    }
+
+    /// parse `brew list --versions` line. e.g. `openssl@1.1 1.1.1j 1.1.1k`
+    fn formula_line(i: &str) -> IResult<&str, Formula> {
+        combinator::map(
+            sequence::pair(token, multi::many0(sequence::preceded(space, token))),
+            |(name, versions)| Formula {
+                name: name.to_owned(),
+                versions: versions.into_iter().map(str::to_owned).collect(),
+                cask: false,
+            },
+        )(i)
+    }
+
+    /// parse `brew list --versions` output.
+    pub(super) fn formula_list(i: &str) -> IResult<&str, Vec<Formula>> {
+        sequence::terminated(
+            multi::separated_list1(character::complete::line_ending, formula_line),
+            combinator::opt(character::complete::line_ending),
+        )(i)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use nom::Err as NomErr;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn test_formula_line() {
+            assert_eq!(
+                formula_line("reattach-to-user-namespace 2.9"),
+                Ok(("", formula("reattach-to-user-namespace", &["2.9"])))
+            );
+            assert_eq!(
+                formula_line("openssl@1.1 1.1.1j 1.1.1k\ngit 2.31.1"),
+                Ok((
+                    "\ngit 2.31.1",
+                    formula("openssl@1.1", &["1.1.1j", "1.1.1k"])
+                ))
+            );
+            assert_eq!(
+                formula_line(" git"),
+                Err(NomErr::Error(nom::error::Error::new(
+                    " git",
+                    nom::error::ErrorKind::TakeWhile1
+                )))
+            );
+        }
+
+        #[test]
+        fn parse_formula_list() {
+            let s = r"git 2.31.1
+gtk+3 3.24.28
+openssl@1.1 1.1.1j 1.1.1k
+reattach-to-user-namespace 2.9
+";
+            assert_eq!(
+                formula_list(s),
+                Ok((
+                    "",
+                    vec![
+                        formula("git", &["2.31.1"]),
+                        formula("gtk+3", &["3.24.28"]),
+                        formula("openssl@1.1", &["1.1.1j", "1.1.1k"]),
+                        formula("reattach-to-user-namespace", &["2.9"]),
+                    ]
+                ))
+            );
+        }
+
+        #[test]
+        fn parse_empty_list() {
+            assert_eq!(super::super::parse_list("").ok(), Some(Vec::new()));
+        }
+
+        fn formula(name: &str, versions: &[&str]) -> Formula {
+            Formula {
+                name: name.to_owned(),
+                versions: versions.iter().map(|v| v.to_string()).collect(),
+                cask: false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::System;
+    use pretty_assertions::assert_eq;
+
+    const STUB_BREW: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/bin/brew");
+
+    fn brew_command(yaml: &str) -> BrewCommand {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn list_and_install_with_stub_brew() {
+        let mut brew = Brew::new(System::new()).with_bin(STUB_BREW);
+
+        let installed = brew.list_installed_formulae().await.unwrap();
+        assert_eq!(
+            installed
+                .iter()
+                .map(|f| (f.name.as_str(), f.version(), f.cask))
+                .collect::<Vec<_>>(),
+            vec![
+                ("git", Some("2.31.1"), false),
+                ("openssl@1.1", Some("1.1.1k"), false),
+                ("iterm2", Some("3.4.4"), true),
+            ]
+        );
+
+        let git = brew_command("{ package: homebrew/core/git }");
+        assert!(installed.iter().any(|f| f.matches(&git)));
+        let iterm = brew_command("{ package: iterm2 }");
+        assert!(!installed.iter().any(|f| f.matches(&iterm)));
+
+        brew.install(&brew_command("{ package: reattach-to-user-namespace }"))
+            .await
+            .unwrap();
+        brew.install(&brew_command("{ package: iterm2, cask: true }"))
+            .await
+            .unwrap();
+        assert!(brew
+            .install(&brew_command("{ package: no-such-formula }"))
+            .await
+            .is_err());
+    }
+}
@@ -1,58 +1,480 @@
-use std::path::PathBuf;
+use serde::Deserialize;
 
-use tokio::process;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    fmt,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
 
-use crate::{prelude::*, system};
+use crate::{
+    config::CargoCommand,
+    prelude::*,
+    system::{self, Command},
+};
 
 const CARGO_BIN: &str = "cargo";
 
+/// Installer name recorded in state.
+pub const CARGO: &str = "cargo";
+
+/// `cargo install --list` only reads local metadata, so it should return quickly.
+const LIST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Install metadata written by cargo, relative to cargo home or `--root`.
+const CRATES2_JSON: &str = ".crates2.json";
+
 /// cargo package.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Package {
     name: String,
-    bin: String,
+    bins: Vec<String>,
     version: semver::Version,
-    local_path: Option<PathBuf>,
+    source: Source,
+    /// Features package was built with. None if read from `cargo install --list`.
+    features: Option<Features>,
+    /// Build profile. None if read from `cargo install --list`.
+    profile: Option<String>,
+}
+
+/// Feature flags package was built with, as recorded in `.crates2.json`.
+#[derive(Deserialize, Debug, PartialEq, Clone, Default)]
+struct Features {
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    all_features: bool,
+    #[serde(default)]
+    no_default_features: bool,
+}
+
+impl Features {
+    fn satisfies(&self, cmd: &CargoCommand) -> bool {
+        let mut installed = self.features.clone();
+        let mut wanted = cmd.features.clone();
+        installed.sort();
+        wanted.sort();
+        installed == wanted
+            && self.all_features == cmd.all_features
+            && self.no_default_features == cmd.no_default_features
+    }
+}
+
+/// Where installed package was built from.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Source {
+    /// crates.io
+    Registry,
+    Path(PathBuf),
+    /// Git repository with commit hash it was built from.
+    Git {
+        url: String,
+        rev: Option<String>,
+    },
+}
+
+impl Source {
+    /// Source id recorded in `.crates2.json`.
+    /// e.g. `registry+https://github.com/rust-lang/crates.io-index`, `git+https://github.com/ymgyt/localenv?branch=main#1a2b3c4d`
+    fn from_source_id(id: &str) -> Self {
+        let mut parts = id.splitn(2, '+');
+        match (parts.next(), parts.next()) {
+            (Some("git"), Some(url)) => {
+                let mut url_rev = url.splitn(2, '#');
+                let url = url_rev.next().unwrap_or_default();
+                Source::Git {
+                    url: url.split('?').next().unwrap_or_default().to_owned(),
+                    rev: url_rev.next().map(str::to_owned),
+                }
+            }
+            (Some("path"), Some(url)) => {
+                Source::Path(PathBuf::from(url.trim_start_matches("file://")))
+            }
+            _ => Source::Registry,
+        }
+    }
+
+    /// Source shown in parentheses of `cargo install --list`. e.g. `https://github.com/ymgyt/localenv#1a2b3c4d`
+    fn parse(s: &str) -> Self {
+        if s.contains("://") {
+            let mut parts = s.splitn(2, '#');
+            Source::Git {
+                url: parts.next().unwrap_or_default().to_owned(),
+                rev: parts.next().map(str::to_owned),
+            }
+        } else {
+            Source::Path(PathBuf::from(s))
+        }
+    }
+
+    /// Whether package installed from this source is what given command asks for.
+    /// Branches cannot be told from installed metadata, so only url and rev are compared.
+    fn satisfies(&self, cmd: &CargoCommand) -> bool {
+        match (self, &cmd.git, &cmd.path) {
+            (Source::Git { url, rev }, Some(want_url), None) => {
+                normalize_git_url(url) == normalize_git_url(want_url)
+                    && match (rev, &cmd.rev) {
+                        (Some(rev), Some(want_rev)) => {
+                            rev.starts_with(want_rev.as_str()) || want_rev.starts_with(rev.as_str())
+                        }
+                        _ => true,
+                    }
+            }
+            (Source::Path(path), None, Some(want_path)) => {
+                same_path(path.as_path(), want_path.as_path())
+            }
+            (Source::Registry, None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Registry => f.write_str("crates.io"),
+            Source::Path(path) => write!(f, "{}", path.display()),
+            Source::Git {
+                url,
+                rev: Some(rev),
+            } => write!(f, "{}#{}", url, rev),
+            Source::Git { url, rev: None } => f.write_str(url),
+        }
+    }
+}
+
+fn normalize_git_url(url: &str) -> &str {
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url)
+}
+
+/// Compare paths after resolving `.` and `..` lexically.
+/// cargo records canonical path, while configured path may contain `..` relative to config directory.
+fn same_path(a: &Path, b: &Path) -> bool {
+    normalize_path(a) == normalize_path(b)
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
 }
 
 impl Package {
-    pub fn bin(&self) -> &str {
-        self.bin.as_str()
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn bins(&self) -> &[String] {
+        self.bins.as_slice()
+    }
+
+    pub fn provides(&self, bin: &str) -> bool {
+        self.bins.iter().any(|b| b == bin)
     }
 
-    fn with_bin<T: Into<String>>(mut self, bin: T) -> Self {
-        self.bin = bin.into();
+    pub fn version(&self) -> &semver::Version {
+        &self.version
+    }
+
+    /// Whether package is installed the way given command asks for.
+    /// Features are compared only when known from install metadata.
+    pub fn satisfies(&self, cmd: &CargoCommand) -> bool {
+        self.source.satisfies(cmd)
+            && self
+                .features
+                .as_ref()
+                .is_none_or(|features| features.satisfies(cmd))
+    }
+
+    /// Source and build options. e.g. `crates.io (features: a,b)`
+    pub fn description(&self) -> String {
+        let mut options = Vec::new();
+        if let Some(features) = &self.features {
+            if !features.features.is_empty() {
+                options.push(format!("features: {}", features.features.join(",")));
+            }
+            if features.all_features {
+                options.push("all features".to_owned());
+            }
+            if features.no_default_features {
+                options.push("no default features".to_owned());
+            }
+        }
+        if let Some(profile) = self.profile.as_ref().filter(|p| *p != "release") {
+            options.push(format!("profile: {}", profile));
+        }
+
+        if options.is_empty() {
+            self.source.to_string()
+        } else {
+            format!("{} ({})", self.source, options.join(", "))
+        }
+    }
+
+    fn with_bins(mut self, bins: Vec<String>) -> Self {
+        self.bins = bins;
         self
     }
 }
 
-pub struct Cargo<Cmd> {
-    cmd: Cmd,
+/// Installed packages per install root. None key is default root.
+#[derive(Debug)]
+pub struct InstalledPackages(HashMap<Option<PathBuf>, Vec<Package>>);
+
+impl InstalledPackages {
+    /// Package of given command under its root.
+    /// Matched by package name, or by bin if no installed package has the name.
+    pub fn find(&self, cmd: &CargoCommand) -> Option<&Package> {
+        let packages = self.0.get(&cmd.root)?;
+        packages
+            .iter()
+            .find(|p| p.name() == cmd.package())
+            .or_else(|| packages.iter().find(|p| p.provides(cmd.bin())))
+    }
+
+    /// Package of given name under given root, or default root if None.
+    pub fn find_by_name(&self, name: &str, root: Option<&Path>) -> Option<&Package> {
+        self.0
+            .get(&root.map(Path::to_path_buf))
+            .and_then(|packages| packages.iter().find(|p| p.name() == name))
+    }
 }
 
-impl Cargo<process::Command> {
-    pub fn new() -> Result<Self> {
-        let cargo_path = system::resolve_binary_path(CARGO_BIN)?;
-        let cmd = process::Command::new(&cargo_path);
+pub struct Cargo<Sys> {
+    sys: Sys,
+}
+
+impl<Sys> Cargo<Sys>
+where
+    Sys: system::CommandApi,
+{
+    pub fn new(sys: Sys) -> Self {
+        Self { sys }
+    }
+
+    /// Run `cargo install` for given command, streaming its output.
+    /// `force` overwrites package installed from other source.
+    pub async fn install(&mut self, cmd: &CargoCommand, force: bool) -> Result<()> {
+        info!("cargo install {}", cmd.package());
+
+        let install = install_command(cmd, force).stream(true);
 
-        Ok(Self { cmd })
+        self.sys
+            .run_command(&install)
+            .await?
+            .ensure_success(&install)
+            .map(|_| ())
     }
 
-    pub async fn list_installed_packages(&mut self) -> Result<Vec<Package>> {
+    /// Run `cargo uninstall` for given package under given root, streaming its output.
+    pub async fn uninstall(&mut self, package: &str, root: Option<&Path>) -> Result<()> {
+        info!("cargo uninstall {}", package);
+
+        let mut uninstall = Command::new(CARGO_BIN).args(["uninstall", package]);
+        if let Some(root) = root {
+            uninstall = uninstall.arg("--root").arg(root);
+        }
+        let uninstall = uninstall.stream(true);
+
+        self.sys
+            .run_command(&uninstall)
+            .await?
+            .ensure_success(&uninstall)
+            .map(|_| ())
+    }
+}
+
+impl<Sys> Cargo<Sys>
+where
+    Sys: system::Api,
+{
+    /// List packages installed under given root, or default root if None.
+    /// Reads `.crates2.json`, falling back to `cargo install --list` output.
+    pub async fn list_installed_packages(&mut self, root: Option<&Path>) -> Result<Vec<Package>> {
+        match self.read_crates2(root) {
+            Ok(Some(packages)) => return Ok(packages),
+            Ok(None) => debug!("{} not found", CRATES2_JSON),
+            Err(err) => warn!(
+                "could not read {}, falling back to cargo install --list: {}",
+                CRATES2_JSON, err
+            ),
+        }
+
+        self.list_installed_packages_from_command(root).await
+    }
+
+    fn read_crates2(&self, root: Option<&Path>) -> Result<Option<Vec<Package>>> {
+        let path = match root {
+            Some(root) => root.join(CRATES2_JSON),
+            None => match cargo_home(&self.sys) {
+                Some(home) => home.join(CRATES2_JSON),
+                None => return Ok(None),
+            },
+        };
+        if self.sys.file_type(&path)?.is_none() {
+            return Ok(None);
+        }
+
+        parse_crates2(&self.sys.read_file(&path)?).map(Some)
+    }
+
+    async fn list_installed_packages_from_command(
+        &mut self,
+        root: Option<&Path>,
+    ) -> Result<Vec<Package>> {
+        let mut install_list = Command::new(CARGO_BIN)
+            .args(["install", "--list"])
+            .timeout(LIST_TIMEOUT);
+        if let Some(root) = root {
+            install_list = install_list.arg("--root").arg(root);
+        }
         let output = self
-            .cmd
-            .args(&["install", "--list"])
-            .output()
-            .await
-            .expect("cargo install --list failed");
+            .sys
+            .run_command(&install_list)
+            .await?
+            .ensure_success(&install_list)?;
 
         let output = String::from_utf8_lossy(output.stdout.as_slice());
 
         parse_install_list(output)
     }
+
+    /// List installed packages of default root and given roots.
+    pub async fn list_installed_packages_by_root<'a, I>(
+        &mut self,
+        roots: I,
+    ) -> Result<InstalledPackages>
+    where
+        I: IntoIterator<Item = Option<&'a Path>>,
+    {
+        let mut installed = InstalledPackages(HashMap::new());
+        for root in std::iter::once(None).chain(roots) {
+            if let Entry::Vacant(entry) = installed.0.entry(root.map(Path::to_path_buf)) {
+                entry.insert(self.list_installed_packages(root).await?);
+            }
+        }
+
+        Ok(installed)
+    }
+}
+
+fn install_command(cmd: &CargoCommand, force: bool) -> Command {
+    let mut install = Command::new(CARGO_BIN).arg("install");
+    if let Some(version) = &cmd.raw_version {
+        install = install.args(["--version", version.as_str()]);
+    }
+    if !cmd.features.is_empty() {
+        install = install.args(["--features", cmd.features.join(",").as_str()]);
+    }
+    if cmd.all_features {
+        install = install.arg("--all-features");
+    }
+    if cmd.no_default_features {
+        install = install.arg("--no-default-features");
+    }
+    if cmd.locked {
+        install = install.arg("--locked");
+    }
+    if let Some(git) = &cmd.git {
+        install = install.args(["--git", git.as_str()]);
+    }
+    if let Some(branch) = &cmd.branch {
+        install = install.args(["--branch", branch.as_str()]);
+    }
+    if let Some(rev) = &cmd.rev {
+        install = install.args(["--rev", rev.as_str()]);
+    }
+    if let Some(path) = &cmd.path {
+        install = install.arg("--path").arg(path);
+    }
+    if let Some(root) = &cmd.root {
+        install = install.arg("--root").arg(root);
+    }
+    if force {
+        install = install.arg("--force");
+    }
+    install.arg(cmd.package())
+}
+
+/// `$CARGO_HOME`, defaults to `~/.cargo`.
+fn cargo_home<Api: system::Api>(sys: &Api) -> Option<PathBuf> {
+    sys.env_var("CARGO_HOME").map(PathBuf::from).or_else(|| {
+        sys.env_var("HOME")
+            .map(|home| PathBuf::from(home).join(".cargo"))
+    })
+}
+
+/// `.crates2.json` content.
+#[derive(Deserialize, Debug)]
+struct CrateListing {
+    /// Keyed by package id. e.g. `bat 0.18.0 (registry+https://github.com/rust-lang/crates.io-index)`
+    installs: BTreeMap<String, InstallInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InstallInfo {
+    #[serde(default)]
+    bins: Vec<String>,
+    #[serde(flatten)]
+    features: Features,
+    profile: Option<String>,
+}
+
+fn parse_crates2(content: &[u8]) -> Result<Vec<Package>> {
+    let listing: CrateListing = serde_json::from_slice(content).map_err(|err| {
+        Error::from(ErrorKind::Internal(format!(
+            "failed to parse {}: {}",
+            CRATES2_JSON, err
+        )))
+    })?;
+
+    listing
+        .installs
+        .into_iter()
+        .map(|(id, info)| {
+            let invalid_id = || {
+                Error::from(ErrorKind::Internal(format!(
+                    "unexpected package id in {}: {}",
+                    CRATES2_JSON, id
+                )))
+            };
+            let mut parts = id.splitn(3, ' ');
+            let name = parts.next().ok_or_else(invalid_id)?;
+            let version = parts
+                .next()
+                .and_then(|v| semver::Version::parse(v).ok())
+                .ok_or_else(invalid_id)?;
+            let source_id = parts
+                .next()
+                .map(|s| s.trim_start_matches('(').trim_end_matches(')'))
+                .ok_or_else(invalid_id)?;
+
+            Ok(Package {
+                name: name.to_owned(),
+                bins: info.bins,
+                version,
+                source: Source::from_source_id(source_id),
+                features: Some(info.features),
+                profile: info.profile,
+            })
+        })
+        .collect()
 }
 
 fn parse_install_list(s: impl AsRef<str>) -> Result<Vec<Package>> {
+    // fresh cargo home has no installed packages.
+    if s.as_ref().trim().is_empty() {
+        return Ok(Vec::new());
+    }
     match parser::package_list(s.as_ref()) {
         Ok((_, packages)) => Ok(packages),
         Err(nom_err) => Err(Error::from(ErrorKind::Internal(format!(
@@ -64,14 +486,13 @@ fn parse_install_list(s: impl AsRef<str>) -> Result<Vec<Package>> {
 
 /// cargo command output parser moduel.
 mod parser {
-    use super::Package;
+    use super::{Package, Source};
     use nom::bytes::complete;
     use nom::character;
     use nom::combinator;
     use nom::multi;
     use nom::sequence;
     use nom::IResult;
-    use std::path::PathBuf;
 
     /// parse cargo package name.
     fn package_name(i: &str) -> IResult<&str, &str> {
@@ -83,7 +504,7 @@ mod parser {
         combinator::map_res(
             sequence::preceded(
                 complete::tag("v"),
-                complete::take_while(|c| ('0'..='9').contains(&c) || c == '.'),
+                complete::take_while(|c: char| c.is_ascii_digit() || c == '.'),
             ),
             semver::Version::parse,
         )(i)
@@ -94,15 +515,15 @@ mod parser {
         complete::take_while(|c: char| c.is_whitespace())(i).map(|(remain, _)| (remain, ()))
     }
 
-    /// parse local path enclosed in parentheses.
-    fn local_path(i: &str) -> IResult<&str, PathBuf> {
+    /// parse local path or git url enclosed in parentheses.
+    fn source(i: &str) -> IResult<&str, Source> {
         combinator::map(
             sequence::delimited(
                 character::complete::char('('),
                 complete::take_until(")"),
                 character::complete::char(')'),
             ),
-            |path| PathBuf::from(path),
+            Source::parse,
         )(i)
     }
 
@@ -130,15 +551,17 @@ mod parser {
                     package_name,
                     space,
                     version,
-                    combinator::opt(sequence::preceded(space, local_path)),
+                    combinator::opt(sequence::preceded(space, source)),
                 )),
                 complete::tag(":"),
             ),
-            |(name, _, v, local_path)| Package {
+            |(name, _, v, source)| Package {
                 name: name.to_owned(),
                 version: v,
-                local_path,
-                bin: "".to_owned(),
+                source: source.unwrap_or(Source::Registry),
+                bins: Vec::new(),
+                features: None,
+                profile: None,
             },
         )(i)
     }
@@ -146,7 +569,7 @@ mod parser {
     /// parse package entry.
     fn package_entry(i: &str) -> IResult<&str, Package> {
         combinator::map(sequence::tuple((package_line, bin_lines)), |(pkg, bins)| {
-            pkg.with_bin(bins.first().expect("at least one binary"))
+            pkg.with_bins(bins)
         })(i)
     }
 
@@ -163,11 +586,11 @@ mod parser {
         use super::*;
         use nom::Err as NomErr;
         use pretty_assertions::assert_eq;
-        use std::ffi::OsStr;
+        use std::{ffi::OsStr, path::PathBuf};
 
         impl Package {
             fn with_local_path<T: ?Sized + AsRef<OsStr>>(mut self, path: &T) -> Self {
-                self.local_path = Some(PathBuf::from(path));
+                self.source = Source::Path(PathBuf::from(path));
                 self
             }
         }
@@ -188,13 +611,23 @@ mod parser {
             );
         }
         #[test]
-        fn test_local_path() {
+        fn test_source() {
             assert_eq!(
-                local_path("(/Users/ymgyt/hello/rust)"),
-                Ok(("", PathBuf::from("/Users/ymgyt/hello/rust")))
+                source("(/Users/ymgyt/hello/rust)"),
+                Ok(("", Source::Path(PathBuf::from("/Users/ymgyt/hello/rust"))))
             );
             assert_eq!(
-                local_path("(/Users/ymgyt/hello/rust"),
+                source("(https://github.com/ymgyt/localenv#1a2b3c4d)"),
+                Ok((
+                    "",
+                    Source::Git {
+                        url: "https://github.com/ymgyt/localenv".to_owned(),
+                        rev: Some("1a2b3c4d".to_owned()),
+                    }
+                ))
+            );
+            assert_eq!(
+                source("(/Users/ymgyt/hello/rust"),
                 Err(NomErr::Error(nom::error::Error::new(
                     "/Users/ymgyt/hello/rust",
                     nom::error::ErrorKind::TakeUntil
@@ -231,11 +664,14 @@ mod parser {
         fn test_package_entry() {
             assert_eq!(
                 package_entry("bat v0.17.1:\n    bat"),
-                Ok(("", pkg_bat().with_bin("bat")))
+                Ok(("", pkg_bat().with_bins(vec!["bat".into()])))
             );
             assert_eq!(
                 package_entry("bat v0.17.1:\n    bat_1\n    bat_2\nripgrep"),
-                Ok(("\nripgrep", pkg_bat().with_bin("bat_1")))
+                Ok((
+                    "\nripgrep",
+                    pkg_bat().with_bins(vec!["bat_1".into(), "bat_2".into()])
+                ))
             );
         }
         #[test]
@@ -274,46 +710,365 @@ ripgrep v12.1.1:
             let want = vec![
                 Package {
                     name: "alacritty".to_owned(),
-                    bin: "alacritty".to_owned(),
+                    bins: bins(&["alacritty"]),
                     version: semver::Version::new(0, 7, 2),
-                    local_path: Some(PathBuf::from("/Users/ymgyt/rs/alacritty/alacritty")),
+                    source: Source::Path(PathBuf::from("/Users/ymgyt/rs/alacritty/alacritty")),
+                    features: None,
+                    profile: None,
                 },
                 Package {
                     name: "bat".to_owned(),
-                    bin: "bat".to_owned(),
+                    bins: bins(&["bat"]),
                     version: semver::Version::new(0, 17, 1),
-                    local_path: None,
+                    source: Source::Registry,
+                    features: None,
+                    profile: None,
                 },
                 Package {
                     name: "cargo-make".to_owned(),
-                    bin: "cargo-make".to_owned(),
+                    bins: bins(&["cargo-make", "makers"]),
                     version: semver::Version::new(0, 32, 12),
-                    local_path: None,
+                    source: Source::Registry,
+                    features: None,
+                    profile: None,
                 },
                 Package {
                     name: "nu".to_owned(),
-                    bin: "nu".to_owned(),
+                    bins: bins(&[
+                        "nu",
+                        "nu_plugin_core_fetch",
+                        "nu_plugin_core_inc",
+                        "nu_plugin_core_match",
+                        "nu_plugin_core_post",
+                        "nu_plugin_core_ps",
+                        "nu_plugin_core_sys",
+                        "nu_plugin_core_textview",
+                        "nu_plugin_extra_binaryview",
+                        "nu_plugin_extra_chart_bar",
+                        "nu_plugin_extra_chart_line",
+                        "nu_plugin_extra_from_bson",
+                        "nu_plugin_extra_from_sqlite",
+                        "nu_plugin_extra_s3",
+                        "nu_plugin_extra_selector",
+                        "nu_plugin_extra_start",
+                        "nu_plugin_extra_to_bson",
+                        "nu_plugin_extra_to_sqlite",
+                        "nu_plugin_extra_tree",
+                        "nu_plugin_extra_xpath",
+                    ]),
                     version: semver::Version::new(0, 29, 1),
-                    local_path: Some(PathBuf::from("/Users/ymgyt/rs/nushell")),
+                    source: Source::Path(PathBuf::from("/Users/ymgyt/rs/nushell")),
+                    features: None,
+                    profile: None,
                 },
                 Package {
                     name: "ripgrep".to_owned(),
-                    bin: "rg".to_owned(),
+                    bins: bins(&["rg"]),
                     version: semver::Version::new(12, 1, 1),
-                    local_path: None,
+                    source: Source::Registry,
+                    features: None,
+                    profile: None,
                 },
             ];
 
             assert_eq!(package_list(s), Ok(("\n", want)));
         }
 
+        #[test]
+        fn parse_empty_install_list() {
+            assert_eq!(super::super::parse_install_list("").ok(), Some(Vec::new()));
+        }
+
         fn pkg_bat() -> Package {
             Package {
                 name: "bat".to_owned(),
                 version: semver::Version::new(0, 17, 1),
-                local_path: None,
-                bin: "".to_owned(),
+                source: Source::Registry,
+                bins: Vec::new(),
+                features: None,
+                profile: None,
             }
         }
+
+        fn bins(bins: &[&str]) -> Vec<String> {
+            bins.iter().map(|b| b.to_string()).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{fake::FakeSystem, CommandOutput, FilePermission};
+    use pretty_assertions::assert_eq;
+
+    fn cargo_command(yaml: &str) -> CargoCommand {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn install_command_options() {
+        let cmd = cargo_command(
+            r"
+package: localenv
+version: 0.1.0
+features: [a, b]
+no_default_features: true
+locked: true
+git: https://github.com/ymgyt/localenv
+branch: main
+root: /opt/localenv
+",
+        );
+
+        assert_eq!(
+            install_command(&cmd, true).get_args(),
+            [
+                "install",
+                "--version",
+                "0.1.0",
+                "--features",
+                "a,b",
+                "--no-default-features",
+                "--locked",
+                "--git",
+                "https://github.com/ymgyt/localenv",
+                "--branch",
+                "main",
+                "--root",
+                "/opt/localenv",
+                "--force",
+                "localenv",
+            ]
+        );
+        assert_eq!(
+            install_command(&cargo_command("{ package: bat }"), false).get_args(),
+            ["install", "bat"]
+        );
+    }
+
+    #[test]
+    fn source_satisfies_command() {
+        let registry = cargo_command("{ package: bat }");
+        let git = cargo_command("{ package: bat, git: 'https://github.com/sharkdp/bat.git' }");
+        let git_rev = cargo_command(
+            "{ package: bat, git: 'https://github.com/sharkdp/bat', rev: 1a2b3c4d5e }",
+        );
+        let path = cargo_command("{ package: bat, path: /src/bat }");
+
+        let installed_git = Source::parse("https://github.com/sharkdp/bat#1a2b3c4d");
+        assert!(installed_git.satisfies(&git));
+        assert!(installed_git.satisfies(&git_rev));
+        assert!(!installed_git.satisfies(&registry));
+        assert!(!Source::parse("https://github.com/sharkdp/bat#ffffffff").satisfies(&git_rev));
+
+        assert!(Source::Registry.satisfies(&registry));
+        assert!(!Source::Registry.satisfies(&path));
+        assert!(Source::parse("/src/bat").satisfies(&path));
+        assert!(!Source::parse("/src/other").satisfies(&path));
+        assert!(Source::parse("/src/bat").satisfies(&cargo_command(
+            "{ package: bat, path: /config/../src/./bat/ }"
+        )));
+    }
+
+    const CRATES2: &str = r#"{
+  "installs": {
+    "bat 0.18.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+      "version_req": null,
+      "bins": ["bat"],
+      "features": ["minimal-application"],
+      "all_features": false,
+      "no_default_features": true,
+      "profile": "release",
+      "target": "x86_64-unknown-linux-gnu",
+      "rustc": "rustc 1.51.0"
+    },
+    "cargo-make 0.32.12 (git+https://github.com/sagiegurari/cargo-make?branch=master#0a1b2c3d)": {
+      "bins": ["cargo-make", "makers"],
+      "features": [],
+      "profile": "release"
+    },
+    "nu 0.29.1 (path+file:///Users/ymgyt/rs/nushell)": {
+      "bins": ["nu"],
+      "features": [],
+      "profile": "dev"
+    }
+  }
+}"#;
+
+    #[test]
+    fn parse_crates2_installs() {
+        let packages = parse_crates2(CRATES2.as_bytes()).unwrap();
+        assert_eq!(
+            packages,
+            vec![
+                Package {
+                    name: "bat".to_owned(),
+                    bins: vec!["bat".to_owned()],
+                    version: semver::Version::new(0, 18, 0),
+                    source: Source::Registry,
+                    features: Some(Features {
+                        features: vec!["minimal-application".to_owned()],
+                        all_features: false,
+                        no_default_features: true,
+                    }),
+                    profile: Some("release".to_owned()),
+                },
+                Package {
+                    name: "cargo-make".to_owned(),
+                    bins: vec!["cargo-make".to_owned(), "makers".to_owned()],
+                    version: semver::Version::new(0, 32, 12),
+                    source: Source::Git {
+                        url: "https://github.com/sagiegurari/cargo-make".to_owned(),
+                        rev: Some("0a1b2c3d".to_owned()),
+                    },
+                    features: Some(Features::default()),
+                    profile: Some("release".to_owned()),
+                },
+                Package {
+                    name: "nu".to_owned(),
+                    bins: vec!["nu".to_owned()],
+                    version: semver::Version::new(0, 29, 1),
+                    source: Source::Path(PathBuf::from("/Users/ymgyt/rs/nushell")),
+                    features: Some(Features::default()),
+                    profile: Some("dev".to_owned()),
+                },
+            ]
+        );
+
+        assert!(packages[0].satisfies(&cargo_command(
+            "{ package: bat, features: [minimal-application], no_default_features: true }"
+        )));
+        assert!(!packages[0].satisfies(&cargo_command(
+            "{ package: bat, features: [minimal-application] }"
+        )));
+        assert!(!packages[0].satisfies(&cargo_command("{ package: bat }")));
+        assert!(!packages[1].satisfies(&cargo_command(
+            "{ package: cargo-make, git: https://github.com/sagiegurari/cargo-make, all_features: true }"
+        )));
+        assert_eq!(
+            packages[0].description(),
+            "crates.io (features: minimal-application, no default features)"
+        );
+        assert_eq!(
+            packages[2].description(),
+            "/Users/ymgyt/rs/nushell (profile: dev)"
+        );
+
+        assert!(parse_crates2(b"{}").is_err());
+        assert!(parse_crates2(br#"{"installs": {"bat": {}}}"#).is_err());
+    }
+
+    #[test]
+    fn find_installed_package() {
+        let mut installed = InstalledPackages(HashMap::new());
+        installed
+            .0
+            .insert(None, parse_crates2(CRATES2.as_bytes()).unwrap());
+        let found = |yaml: &str| installed.find(&cargo_command(yaml)).map(Package::name);
+
+        assert_eq!(found("{ package: cargo-make }"), Some("cargo-make"));
+        assert_eq!(
+            found("{ package: cargo-make, bin: makers }"),
+            Some("cargo-make")
+        );
+        // matched by package name even if configured bin is not provided.
+        assert_eq!(found("{ package: bat, bin: batcat }"), Some("bat"));
+        // matched by bin when package name differs.
+        assert_eq!(
+            found("{ package: renamed, bin: makers }"),
+            Some("cargo-make")
+        );
+        assert_eq!(found("{ package: ripgrep, bin: rg }"), None);
+        assert_eq!(found("{ package: nu, root: /opt/cargo }"), None);
+
+        assert!(installed.find_by_name("nu", None).is_some());
+        assert!(installed
+            .find_by_name("nu", Some(Path::new("/opt/cargo")))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn uninstall_from_root() {
+        let sys = FakeSystem::new().with_command_output(
+            &["cargo", "uninstall", "nu", "--root", "/opt/cargo"],
+            CommandOutput {
+                code: Some(0),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            },
+        );
+        Cargo::new(sys)
+            .uninstall("nu", Some(Path::new("/opt/cargo")))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn list_installed_packages_from_crates2() {
+        // no command output is scripted, so running cargo would fail.
+        let sys = FakeSystem::new().with_file(
+            "/opt/cargo/.crates2.json",
+            CRATES2,
+            FilePermission::UnixMode(0o644),
+        );
+        let mut cargo = Cargo::new(sys);
+
+        let packages = cargo
+            .list_installed_packages(Some(Path::new("/opt/cargo")))
+            .await
+            .unwrap();
+        assert_eq!(
+            packages.iter().map(Package::name).collect::<Vec<_>>(),
+            ["bat", "cargo-make", "nu"]
+        );
+        assert!(packages[1].provides("makers"));
+    }
+
+    #[tokio::test]
+    async fn list_installed_packages_of_cargo_home() {
+        // no command output is scripted, so packages come from `.crates2.json` of cargo home.
+        for (env, value, path) in [
+            (
+                "CARGO_HOME",
+                "/opt/cargo-home",
+                "/opt/cargo-home/.crates2.json",
+            ),
+            ("HOME", "/home/alice", "/home/alice/.cargo/.crates2.json"),
+        ] {
+            let sys = FakeSystem::new().with_env(env, value).with_file(
+                path,
+                CRATES2,
+                FilePermission::UnixMode(0o644),
+            );
+            let packages = Cargo::new(sys).list_installed_packages(None).await.unwrap();
+            assert_eq!(packages.len(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn list_installed_packages_falls_back_to_command() {
+        let sys = FakeSystem::new()
+            .with_file(
+                "/opt/cargo/.crates2.json",
+                "not json",
+                FilePermission::UnixMode(0o644),
+            )
+            .with_command_output(
+                &["cargo", "install", "--list", "--root", "/opt/cargo"],
+                CommandOutput {
+                    code: Some(0),
+                    stdout: b"ripgrep v12.1.1:\n    rg\n".to_vec(),
+                    stderr: Vec::new(),
+                },
+            );
+        let mut cargo = Cargo::new(sys);
+
+        let packages = cargo
+            .list_installed_packages(Some(Path::new("/opt/cargo")))
+            .await
+            .unwrap();
+        assert_eq!(packages.len(), 1);
+        assert!(packages[0].provides("rg"));
     }
 }
@@ -0,0 +1,350 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    config::GoCommand,
+    prelude::*,
+    system::{self, Command},
+};
+
+const GO_BIN: &str = "go";
+
+/// Installer name recorded in state.
+pub const GO: &str = "go";
+
+/// `go env` and `go version -m` only read local files, so they should return quickly.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Version installed when command does not pin one.
+const LATEST: &str = "latest";
+
+/// go binary installed by `go install`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Binary {
+    /// Build info embedded in binary. None if binary was not built by go modules.
+    module: Option<ModuleInfo>,
+}
+
+/// build info reported by `go version -m`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ModuleInfo {
+    /// Main package path. e.g. `github.com/ahmetb/kubectx/cmd/kubectx`
+    path: String,
+    version: String,
+}
+
+impl Binary {
+    pub fn version(&self) -> Option<&str> {
+        self.module.as_ref().map(|m| m.version.as_str())
+    }
+
+    /// Module version as semver. None for `(devel)` builds and binaries without build info.
+    pub fn semver(&self) -> Option<semver::Version> {
+        let version = self.version()?.strip_prefix('v')?;
+        semver::Version::parse(version).ok()
+    }
+}
+
+pub struct Go<Sys> {
+    sys: Sys,
+    go: PathBuf,
+    goroot: Option<PathBuf>,
+}
+
+impl<Sys> Go<Sys>
+where
+    Sys: system::CommandApi,
+{
+    /// Use `go` of given GOROOT, or one found in PATH.
+    pub fn new(sys: Sys, goroot: Option<&Path>) -> Self {
+        let go = match goroot {
+            Some(goroot) => goroot.join("bin").join(GO_BIN),
+            None => PathBuf::from(GO_BIN),
+        };
+        Self {
+            sys,
+            go,
+            goroot: goroot.map(Path::to_path_buf),
+        }
+    }
+
+    /// `go` run against configured GOROOT. GOROOT inherited from environment is overridden,
+    /// and go.mod or go.work around the current directory can not switch toolchain.
+    fn command(&self) -> Command {
+        let cmd = Command::new(&self.go);
+        match self.goroot {
+            Some(ref goroot) => cmd.env("GOROOT", goroot).current_dir(goroot),
+            None => cmd,
+        }
+    }
+
+    /// Run `go install <module>@<version>`, streaming its output.
+    pub async fn install(&mut self, cmd: &GoCommand) -> Result<()> {
+        let target = format!(
+            "{}@{}",
+            cmd.package(),
+            cmd.version.as_deref().unwrap_or(LATEST)
+        );
+        info!("go install {}", target);
+
+        let install = self
+            .command()
+            .args(["install", target.as_str()])
+            .stream(true);
+
+        self.sys
+            .run_command(&install)
+            .await?
+            .ensure_success(&install)
+            .map(|_| ())
+    }
+}
+
+impl<Sys> Go<Sys>
+where
+    Sys: system::Api,
+{
+    /// Directory `go install` writes binaries to. `$GOBIN` or `$GOPATH/bin`.
+    pub async fn bin_dir(&mut self) -> Result<PathBuf> {
+        let env = self
+            .command()
+            .args(["env", "GOBIN", "GOPATH"])
+            .timeout(QUERY_TIMEOUT);
+        let output = self.sys.run_command(&env).await?.ensure_success(&env)?;
+
+        parse_bin_dir(String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Look up installed binary of given command in bin dir.
+    pub async fn installed_binary(
+        &mut self,
+        bin_dir: &Path,
+        cmd: &GoCommand,
+    ) -> Result<Option<Binary>> {
+        let path = bin_dir.join(cmd.bin());
+        if self.sys.file_type(&path)?.is_none() {
+            return Ok(None);
+        }
+
+        let version = self
+            .command()
+            .args(["version", "-m"])
+            .arg(&path)
+            .timeout(QUERY_TIMEOUT);
+        let output = self.sys.run_command(&version).await?;
+        let module = if output.success() {
+            parse_module_info(String::from_utf8_lossy(&output.stdout))
+        } else {
+            debug!("{} has no build info", path.display());
+            None
+        };
+
+        if let Some(module) = &module {
+            if module.path != cmd.package() {
+                warn!(
+                    "{} is built from {}, not {}",
+                    path.display(),
+                    module.path,
+                    cmd.package()
+                );
+            }
+        }
+
+        Ok(Some(Binary { module }))
+    }
+}
+
+/// `go env GOBIN GOPATH` prints one value per line. GOBIN is empty unless set.
+fn parse_bin_dir(s: impl AsRef<str>) -> Result<PathBuf> {
+    let mut lines = s.as_ref().lines().map(str::trim);
+    let gobin = lines.next().unwrap_or_default();
+    if !gobin.is_empty() {
+        return Ok(PathBuf::from(gobin));
+    }
+
+    // binaries go to first element of GOPATH list.
+    match lines
+        .next()
+        .and_then(|gopath| std::env::split_paths(gopath).next())
+    {
+        Some(gopath) if !gopath.as_os_str().is_empty() => Ok(gopath.join("bin")),
+        _ => Err(Error::from(ErrorKind::Internal(format!(
+            "neither GOBIN nor GOPATH is defined: {:?}",
+            s.as_ref()
+        )))),
+    }
+}
+
+fn parse_module_info(s: impl AsRef<str>) -> Option<ModuleInfo> {
+    match parser::module_info(s.as_ref()) {
+        Ok((_, info)) => Some(info),
+        Err(nom_err) => {
+            debug!("failed to parse go build info: {:?}", nom_err);
+            None
+        }
+    }
+}
+
+/// go command output parser module.
+mod parser {
+    use super::ModuleInfo;
+    use nom::bytes::complete;
+    use nom::character;
+    use nom::combinator;
+    use nom::sequence;
+    use nom::IResult;
+
+    /// parse tab separated field.
+    fn field(i: &str) -> IResult<&str, &str> {
+        complete::take_while1(|c: char| !c.is_whitespace())(i)
+    }
+
+    /// consume rest of line including line ending.
+    fn rest_of_line(i: &str) -> IResult<&str, ()> {
+        combinator::map(
+            sequence::pair(
+                complete::take_till(|c| c == '\n'),
+                combinator::opt(character::complete::newline),
+            ),
+            |_| (),
+        )(i)
+    }
+
+    /// parse build info line like `\t<key>\t<value>...`, returning value.
+    fn info_line<'a>(key: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+        sequence::preceded(
+            sequence::tuple((
+                character::complete::space1,
+                complete::tag(key),
+                character::complete::space1,
+            )),
+            field,
+        )
+    }
+
+    /// parse `go version -m <bin>` output.
+    pub(super) fn module_info(i: &str) -> IResult<&str, ModuleInfo> {
+        combinator::map(
+            sequence::tuple((
+                // `<bin>: go1.16.3`
+                rest_of_line,
+                sequence::terminated(info_line("path"), rest_of_line),
+                sequence::pair(
+                    info_line("mod"),
+                    sequence::preceded(character::complete::space1, field),
+                ),
+            )),
+            |(_, path, (_module, version))| ModuleInfo {
+                path: path.to_owned(),
+                version: version.to_owned(),
+            },
+        )(i)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn test_info_line() {
+            assert_eq!(
+                info_line("path")("\tpath\tgithub.com/ahmetb/kubectx/cmd/kubectx\n"),
+                Ok(("\n", "github.com/ahmetb/kubectx/cmd/kubectx"))
+            );
+            assert!(info_line("mod")("\tpath\tgithub.com/ahmetb/kubectx").is_err());
+        }
+
+        #[test]
+        fn parse_module_info() {
+            let s = "/home/ymgyt/go/bin/kubectx: go1.16.3
+\tpath\tgithub.com/ahmetb/kubectx/cmd/kubectx
+\tmod\tgithub.com/ahmetb/kubectx\tv0.9.3\th1:Dbi1gMLyCyF0yYj2qGDgQKdZNbvt7jlhGVIFThDJ+Y8=
+\tdep\tgithub.com/fatih/color\tv1.9.0\th1:8xPHl4/q1VyqGIPif1F+1V3Y3lSmrq01EabUW3CoW5s=
+";
+            assert_eq!(
+                module_info(s).map(|(_, info)| info),
+                Ok(ModuleInfo {
+                    path: "github.com/ahmetb/kubectx/cmd/kubectx".to_owned(),
+                    version: "v0.9.3".to_owned(),
+                })
+            );
+        }
+
+        #[test]
+        fn parse_module_info_without_mod() {
+            // binaries built outside module mode have no mod line.
+            assert!(module_info("/usr/local/bin/foo: go1.16.3\n\tpath\tfoo\n").is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{fake::FakeSystem, CommandOutput, FilePermission};
+    use pretty_assertions::assert_eq;
+
+    fn success(stdout: &str) -> CommandOutput {
+        CommandOutput {
+            code: Some(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn installed_binary() {
+        let sys = FakeSystem::new()
+            .with_file("/go/bin/kubectx", "", FilePermission::UnixMode(0o755))
+            .with_command_output(
+                &["/usr/local/go/bin/go", "env", "GOBIN", "GOPATH"],
+                success("\n/go\n"),
+            )
+            .with_command_output(
+                &["/usr/local/go/bin/go", "version", "-m", "/go/bin/kubectx"],
+                success(
+                    "/go/bin/kubectx: go1.16.3\n\tpath\tgithub.com/ahmetb/kubectx/cmd/kubectx\n\tmod\tgithub.com/ahmetb/kubectx\tv0.9.3\th1:xxx\n",
+                ),
+            );
+        let mut go = Go::new(sys, Some(Path::new("/usr/local/go")));
+        let kubectx: GoCommand =
+            serde_yaml::from_str("{ package: github.com/ahmetb/kubectx/cmd/kubectx }").unwrap();
+        let kubens: GoCommand =
+            serde_yaml::from_str("{ package: github.com/ahmetb/kubectx/cmd/kubens }").unwrap();
+
+        let bin_dir = go.bin_dir().await.unwrap();
+        assert_eq!(bin_dir, PathBuf::from("/go/bin"));
+        assert_eq!(
+            go.installed_binary(&bin_dir, &kubectx)
+                .await
+                .unwrap()
+                .as_ref()
+                .and_then(Binary::version),
+            Some("v0.9.3")
+        );
+        assert_eq!(
+            go.installed_binary(&bin_dir, &kubectx)
+                .await
+                .unwrap()
+                .and_then(|binary| binary.semver()),
+            semver::Version::parse("0.9.3").ok()
+        );
+        assert_eq!(go.installed_binary(&bin_dir, &kubens).await.unwrap(), None);
+    }
+
+    #[test]
+    fn bin_dir() {
+        assert_eq!(
+            parse_bin_dir("/opt/gobin\n/home/ymgyt/go\n").ok(),
+            Some(PathBuf::from("/opt/gobin"))
+        );
+        assert_eq!(
+            parse_bin_dir("\n/home/ymgyt/go:/home/ymgyt/other\n").ok(),
+            Some(PathBuf::from("/home/ymgyt/go/bin"))
+        );
+        assert!(parse_bin_dir("\n\n").is_err());
+    }
+}
@@ -1,2 +1,19 @@
+use crate::config::Command;
+
+mod brew;
+pub use brew::{Brew, BREW};
+
 mod cargo;
-pub use cargo::{Cargo, Package as CargoPackage};
+pub use cargo::{Cargo, CARGO};
+
+mod go;
+pub use go::{Go, GO};
+
+/// Installer name recorded in state for given command.
+pub fn name(cmd: &Command) -> &'static str {
+    match cmd {
+        Command::Cargo(_) => CARGO,
+        Command::Brew(_) => BREW,
+        Command::Go(_) => GO,
+    }
+}
@@ -1,8 +1,12 @@
 mod operation;
 pub use operation::{
-    CommandOperation, FilesystemOperation, Operation, OperationChain, OperationKind,
+    Change, CommandOperation, FilesystemOperation, Operation, OperationChain, OperationKind,
+    SkipReason,
 };
 
+mod diff;
+pub use diff::FileDiff;
+
 mod plan;
 pub use plan::plan;
 
@@ -12,4 +16,783 @@ pub use apply::{apply, ApplyParam};
 mod display;
 pub use display::{display, DisplayParam};
 
+mod condition;
+mod template;
+
 pub mod installer;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{self, Config},
+        state::{BackupStore, ManagedEntry, ManagedKind, ManagedPackage, State},
+        system::{
+            fake::{Call, FakeEntry, FakeSystem},
+            CommandOutput, FilePermission, Os,
+        },
+    };
+    use std::{ffi::OsString, path::PathBuf};
+
+    /// go of goroot in example config.
+    const GO: &str = "/usr/local/go/bin/go";
+    const KUBECTX: &str = "github.com/ahmetb/kubectx/cmd/kubectx@latest";
+
+    fn success(stdout: &str) -> CommandOutput {
+        CommandOutput {
+            code: Some(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        }
+    }
+
+    /// Copy sources of file entries into fake system, which plan and apply read through it.
+    fn with_sources(sys: FakeSystem, config: &Config) -> FakeSystem {
+        config
+            .spec
+            .filesystem
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                config::FilesystemEntry::File(file) => Some(file.src_path(&config.root_dir)),
+                _ => None,
+            })
+            .fold(sys, |sys, src| {
+                let content = std::fs::read(&src).unwrap();
+                sys.with_file(src, content, FilePermission::UnixMode(0o644))
+            })
+    }
+
+    #[tokio::test]
+    async fn plan_with_profile() {
+        let mut config = Config::load_from_dir(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config_example"),
+            Os::Linux,
+        )
+        .await
+        .unwrap();
+        config.select_profile("ci").unwrap();
+
+        // brew and go are not run since their commands are skipped.
+        let mut sys = FakeSystem::new()
+            .with_os(Os::Linux)
+            .with_command_output(&["cargo", "install", "--list"], success(""));
+        let chain = plan(&mut sys, &config, &State::default()).await.unwrap();
+
+        let ci = || "ci".to_owned();
+        assert_eq!(
+            chain.skipped(),
+            &[
+                (
+                    "alacritty config file.".to_owned(),
+                    SkipReason::NotIncluded { profile: ci() }
+                ),
+                (
+                    "nushell symlink for mac.".to_owned(),
+                    SkipReason::Condition {
+                        unmet: "os is linux, not mac".to_owned()
+                    }
+                ),
+                (
+                    "alacritty".to_owned(),
+                    SkipReason::NotIncluded { profile: ci() }
+                ),
+                (
+                    "reattach-to-user-namespace".to_owned(),
+                    SkipReason::Condition {
+                        unmet: "os is linux, not mac".to_owned()
+                    }
+                ),
+                (
+                    "github.com/ahmetb/kubectx/cmd/kubectx".to_owned(),
+                    SkipReason::NotIncluded { profile: ci() }
+                ),
+            ]
+        );
+        assert!(!chain.operations().iter().any(|ops| matches!(
+            ops.kind(),
+            OperationKind::Command(CommandOperation::Install { cmd }) if cmd.package() == "alacritty"
+        )));
+
+        // state is refreshed only for planned commands, so brew and go are not run by apply either.
+        let mut chain = chain;
+        apply(ApplyParam {
+            system: &mut sys,
+            config: &config,
+            operation_chain: &mut chain,
+            backup_store: &mut BackupStore::new("/state"),
+            state: &mut State::default(),
+            dry_run: false,
+            prune: false,
+            jobs: 1,
+        })
+        .await
+        .unwrap();
+        assert!(sys
+            .command_lines()
+            .iter()
+            .all(|line| line[0] != "brew" && line[0] != GO));
+    }
+
+    #[tokio::test]
+    async fn skip_commands_of_unavailable_installer() {
+        let mut config = Config::load_from_dir(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config_example"),
+            Os::Mac,
+        )
+        .await
+        .unwrap();
+        config.select_profile("work").unwrap();
+
+        // brew is not scripted, so listing formulae fails like brew missing from $PATH.
+        let mut sys = FakeSystem::new()
+            .with_os(Os::Mac)
+            .with_command_output(&["cargo", "install", "--list"], success(""))
+            .with_command_output(&[GO, "env", "GOBIN", "GOPATH"], success("\n/go\n"));
+        let chain = plan(&mut sys, &config, &State::default()).await.unwrap();
+
+        assert!(chain.skipped().iter().any(|(entry, reason)| {
+            entry == "reattach-to-user-namespace"
+                && matches!(reason, SkipReason::InstallerUnavailable { installer: name, .. } if name == installer::BREW)
+        }));
+        assert!(!chain.operations().iter().any(|ops| matches!(
+            ops.kind(),
+            OperationKind::Command(CommandOperation::Install {
+                cmd: config::Command::Brew(_)
+            })
+        )));
+    }
+
+    #[tokio::test]
+    async fn plan_and_apply_example_config() {
+        let config = Config::load_from_dir(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config_example"),
+            Os::Mac,
+        )
+        .await
+        .unwrap();
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        let nu_config = home.join(".config/nu/config.toml");
+        let nu_link = home.join("Library/Application Support/org.nushell.nu/config.toml");
+
+        let to_install = [
+            "alacritty",
+            "cargo-add",
+            "cargo-generate",
+            "cargo-make",
+            "exa",
+            "git-trim",
+            "ripgrep",
+            "starship",
+            "watchexec",
+            "dummy",
+        ];
+        let sys = to_install
+            .iter()
+            .fold(
+                FakeSystem::new()
+                    .with_os(Os::Mac)
+                    .with_directory(home.clone(), FilePermission::UnixMode(0o755))
+                    .with_file(
+                        home.join(".tmux.conf"),
+                        "old",
+                        FilePermission::UnixMode(0o600),
+                    )
+                    .with_directory(nu_link.parent().unwrap(), FilePermission::UnixMode(0o700))
+                    .with_symbolic_link("/somewhere/else", nu_link.clone())
+                    .with_command_output(
+                        &["cargo", "install", "--list"],
+                        success("bat v0.17.1:\n    bat\n"),
+                    )
+                    .with_command_output(&["brew", "list", "--formula", "--versions"], success(""))
+                    .with_command_output(&["brew", "list", "--cask", "--versions"], success(""))
+                    .with_command_output(
+                        &["brew", "install", "reattach-to-user-namespace"],
+                        success(""),
+                    )
+                    .with_command_output(&[GO, "env", "GOBIN", "GOPATH"], success("\n/go\n"))
+                    .with_command_output(&[GO, "install", KUBECTX], success("")),
+                |sys, package| sys.with_command_output(&["cargo", "install", package], success("")),
+            )
+            .with_command_output(
+                &["cargo", "install", "--version", "0.18.0", "bat"],
+                success(""),
+            );
+        let mut sys = with_sources(sys, &config);
+
+        let mut state = State::default();
+        let mut chain = plan(&mut sys, &config, &state).await.unwrap();
+        let mut backup_store = BackupStore::new("/state");
+        let changes: Vec<(Change, PathBuf)> = chain
+            .operations()
+            .iter()
+            .filter_map(|ops| match ops.kind() {
+                OperationKind::Filesystem(FilesystemOperation::CreateFile {
+                    entry,
+                    change,
+                    ..
+                }) => Some((*change, entry.dest_path().unwrap())),
+                OperationKind::Filesystem(FilesystemOperation::CreateSymbolicLink {
+                    entry,
+                    change,
+                }) => Some((*change, entry.link_path().unwrap())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (Change::Create, home.join(".config/alacritty/alacritty.yml")),
+                (Change::Create, nu_config.clone()),
+                (Change::Update, nu_link.clone()),
+                (Change::Update, home.join(".tmux.conf")),
+            ]
+        );
+        apply(ApplyParam {
+            system: &mut sys,
+            config: &config,
+            operation_chain: &mut chain,
+            backup_store: &mut backup_store,
+            state: &mut state,
+            dry_run: false,
+            prune: true,
+            jobs: 1,
+        })
+        .await
+        .unwrap();
+
+        assert!(chain
+            .operations()
+            .iter()
+            .all(|ops| ops.result().unwrap().is_ok()));
+        assert_eq!(
+            sys.entries()
+                .keys()
+                .filter(|path| path.starts_with(&home) && !path.starts_with(&config.root_dir))
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![
+                home.clone(),
+                home.join(".config"),
+                home.join(".config/alacritty"),
+                home.join(".config/alacritty/alacritty.yml"),
+                home.join(".config/nu"),
+                nu_config.clone(),
+                home.join(".local/bin"),
+                home.join(".tmux.conf"),
+                nu_link.parent().unwrap().to_path_buf(),
+                nu_link.clone(),
+            ]
+        );
+        assert_eq!(
+            sys.entry(home.join(".config/nu")),
+            Some(&FakeEntry::Directory {
+                permission: FilePermission::UnixMode(0o755)
+            })
+        );
+        assert_eq!(
+            sys.entry(&nu_link),
+            Some(&FakeEntry::SymbolicLink {
+                original: nu_config.clone()
+            })
+        );
+        assert_eq!(
+            sys.entry(home.join(".local/bin")),
+            Some(&FakeEntry::Directory {
+                permission: FilePermission::UnixMode(0o755)
+            })
+        );
+        assert_eq!(
+            sys.entry(home.join(".tmux.conf")),
+            Some(&FakeEntry::File {
+                content: std::fs::read(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/config_example/static/tmux/tmux.conf"
+                ))
+                .unwrap(),
+                permission: FilePermission::UnixMode(0o644),
+            })
+        );
+
+        let command_line = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        let list_installed = vec![
+            command_line(&["cargo", "install", "--list"]),
+            command_line(&["brew", "list", "--formula", "--versions"]),
+            command_line(&["brew", "list", "--cask", "--versions"]),
+            command_line(&[GO, "env", "GOBIN", "GOPATH"]),
+        ];
+        let mut want = list_installed.clone();
+        want.extend(vec![
+            command_line(&["cargo", "install", "alacritty"]),
+            // bat 0.17.1 is upgraded in config order, right after alacritty.
+            command_line(&["cargo", "install", "--version", "0.18.0", "bat"]),
+            command_line(&["cargo", "install", "cargo-add"]),
+            command_line(&["cargo", "install", "cargo-generate"]),
+            command_line(&["cargo", "install", "cargo-make"]),
+            command_line(&["cargo", "install", "exa"]),
+            command_line(&["cargo", "install", "git-trim"]),
+            command_line(&["cargo", "install", "ripgrep"]),
+            command_line(&["cargo", "install", "starship"]),
+            command_line(&["cargo", "install", "watchexec"]),
+            command_line(&["cargo", "install", "dummy"]),
+            command_line(&["brew", "install", "reattach-to-user-namespace"]),
+            command_line(&[GO, "install", KUBECTX]),
+        ]);
+        // refresh installed versions for state.
+        want.extend(list_installed);
+        assert_eq!(sys.command_lines(), want);
+
+        assert_eq!(state.config_revision, Some(config.revision.clone()));
+        assert_eq!(
+            state
+                .filesystem
+                .iter()
+                .map(|entry| (entry.path.clone(), entry.created))
+                .collect::<Vec<_>>(),
+            vec![
+                (home.join(".config/alacritty/alacritty.yml"), true),
+                (nu_config.clone(), true),
+                (nu_link.clone(), false),
+                (home.join(".tmux.conf"), false),
+                (home.join(".local/bin"), true),
+            ]
+        );
+        let bat = state.packages.iter().find(|p| p.package == "bat").unwrap();
+        assert_eq!(bat.version, Some("0.17.1".to_owned()));
+        assert!(!bat.created);
+        assert!(state
+            .packages
+            .iter()
+            .any(|p| p.installer == installer::BREW && p.created));
+        assert_eq!(state.packages.len(), 13);
+
+        let backups = backup_store.list(&sys).unwrap();
+        assert_eq!(
+            backups[0]
+                .manifest
+                .entries
+                .iter()
+                .map(|entry| entry.path.clone())
+                .collect::<Vec<_>>(),
+            vec![nu_link.clone(), home.join(".tmux.conf")]
+        );
+
+        // filesystem is already up to date on second run.
+        let chain = plan(&mut sys, &config, &state).await.unwrap();
+        assert!(chain
+            .operations()
+            .iter()
+            .all(|ops| matches!(ops.kind(), OperationKind::Command(_))));
+    }
+
+    #[tokio::test]
+    async fn prune_entries_dropped_from_config() {
+        let config = Config::load_from_dir(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config_example"),
+            Os::Linux,
+        )
+        .await
+        .unwrap();
+        let mut sys = FakeSystem::new()
+            .with_file("/old/created", "x", FilePermission::UnixMode(0o644))
+            .with_file("/old/overwritten", "x", FilePermission::UnixMode(0o644))
+            .with_symbolic_link("/old/created", "/old/link")
+            .with_command_output(
+                &["cargo", "install", "--list"],
+                success("old-crate v0.1.0:\n    old\n"),
+            )
+            .with_command_output(&["cargo", "uninstall", "old-crate"], success(""))
+            .with_command_output(
+                &["brew", "list", "--formula", "--versions"],
+                success("reattach-to-user-namespace 2.9\n"),
+            )
+            .with_command_output(&["brew", "list", "--cask", "--versions"], success(""))
+            .with_command_output(&[GO, "env", "GOBIN", "GOPATH"], success("\n/go\n"));
+        let mut backup_store = BackupStore::new("/state");
+        let managed_file = |path: &str, created| ManagedEntry {
+            path: PathBuf::from(path),
+            kind: ManagedKind::File {
+                content_hash: String::new(),
+                mode: "0644".to_owned(),
+            },
+            created,
+        };
+        let old_crate = ManagedPackage {
+            installer: installer::CARGO.to_owned(),
+            package: "old-crate".to_owned(),
+            version: Some("0.1.0".to_owned()),
+            root: None,
+            created: true,
+        };
+        let mut state = State {
+            filesystem: vec![
+                managed_file("/old/created", true),
+                managed_file("/old/overwritten", false),
+                managed_file("/old/already_removed", true),
+                ManagedEntry {
+                    path: PathBuf::from("/old/link"),
+                    kind: ManagedKind::SymbolicLink {
+                        original: PathBuf::from("/old/created"),
+                    },
+                    created: true,
+                },
+            ],
+            packages: vec![old_crate.clone()],
+            ..Default::default()
+        };
+
+        let chain = plan(&mut sys, &config, &state).await.unwrap();
+        let removals = chain
+            .operations()
+            .iter()
+            .filter_map(|ops| match ops.kind() {
+                OperationKind::Filesystem(FilesystemOperation::RemoveFile { path })
+                | OperationKind::Filesystem(FilesystemOperation::RemoveSymbolicLink { path }) => {
+                    Some(path.display().to_string())
+                }
+                OperationKind::Command(CommandOperation::Uninstall { package }) => {
+                    Some(package.package.clone())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(removals, vec!["/old/created", "/old/link", "old-crate"]);
+        assert_eq!(chain.removal_count(), 3);
+
+        // apply removals only.
+        let removal_chain = || {
+            let mut chain = OperationChain::new();
+            chain.add(Operation::remove_file(PathBuf::from("/old/created")));
+            chain.add(Operation::remove_symbolic_link(PathBuf::from("/old/link")));
+            chain.add(Operation::uninstall_command(old_crate.clone()));
+            chain
+        };
+
+        // removals are skipped without prune.
+        let mut chain = removal_chain();
+        apply(ApplyParam {
+            system: &mut sys,
+            config: &config,
+            operation_chain: &mut chain,
+            backup_store: &mut backup_store,
+            state: &mut state,
+            dry_run: false,
+            prune: false,
+            jobs: 1,
+        })
+        .await
+        .unwrap();
+        assert!(chain.operations().iter().all(|ops| ops.result().is_none()
+            && matches!(ops.skipped(), Some(SkipReason::PruneDisabled))));
+        display(DisplayParam {
+            system: &mut sys,
+            config: &config,
+            operation_chain: &chain,
+        })
+        .await
+        .unwrap();
+        assert!(sys.calls().iter().any(|call| matches!(
+            call,
+            Call::Display(msg) if msg.contains("Skipped, removal runs only with --prune")
+        )));
+        assert!(sys.entry("/old/created").is_some());
+        assert_eq!(state.filesystem.len(), 2);
+        assert!(state.packages.contains(&old_crate));
+
+        let mut chain = removal_chain();
+        apply(ApplyParam {
+            system: &mut sys,
+            config: &config,
+            operation_chain: &mut chain,
+            backup_store: &mut backup_store,
+            state: &mut state,
+            dry_run: false,
+            prune: true,
+            jobs: 1,
+        })
+        .await
+        .unwrap();
+
+        assert!(chain
+            .operations()
+            .iter()
+            .all(|ops| ops.result().unwrap().is_ok()));
+        assert_eq!(sys.entry("/old/created"), None);
+        assert_eq!(sys.entry("/old/link"), None);
+        assert!(sys.entry("/old/overwritten").is_some());
+        assert!(sys.command_lines().contains(&vec![
+            "cargo".into(),
+            "uninstall".into(),
+            "old-crate".into()
+        ]));
+        assert!(state.filesystem.is_empty());
+        assert!(!state.packages.contains(&old_crate));
+        assert_eq!(
+            backup_store.list(&sys).unwrap()[0].manifest.entries.len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_commands_concurrently() {
+        let config = Config::load_from_dir(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config_example"),
+            Os::Linux,
+        )
+        .await
+        .unwrap();
+        let mut sys = FakeSystem::new()
+            .with_command_output(&["cargo", "install", "exa"], success("exa installed\n"))
+            .with_command_output(
+                &["cargo", "install", "dummy"],
+                CommandOutput {
+                    code: Some(101),
+                    stdout: Vec::new(),
+                    stderr: b"error: could not find `dummy` in registry\n".to_vec(),
+                },
+            )
+            .with_command_output(&[GO, "install", KUBECTX], success(""));
+        let cmd = |package: &str| {
+            config
+                .spec
+                .commands
+                .cargo
+                .iter()
+                .flatten()
+                .find(|c| c.package() == package)
+                .cloned()
+                .map(config::Command::Cargo)
+                .unwrap()
+        };
+        let mut chain = OperationChain::new();
+        chain.add(Operation::install_command(cmd("exa")));
+        chain.add(Operation::install_command(cmd("dummy")));
+        chain.add(Operation::install_command(config::Command::Go(
+            config.spec.commands.go.as_ref().unwrap()[0].clone(),
+        )));
+        let mut state = State::default();
+
+        apply(ApplyParam {
+            system: &mut sys,
+            config: &config,
+            operation_chain: &mut chain,
+            backup_store: &mut BackupStore::new("/state"),
+            state: &mut state,
+            dry_run: false,
+            prune: false,
+            jobs: 2,
+        })
+        .await
+        .unwrap();
+
+        let ops = chain.operations();
+        assert!(ops[0].result().unwrap().is_ok());
+        assert_eq!(ops[0].output(), Some("exa installed\n"));
+        assert!(ops[1].result().unwrap().is_err());
+        assert_eq!(
+            ops[1].output(),
+            Some("error: could not find `dummy` in registry\n")
+        );
+        assert!(ops[2].result().unwrap().is_ok());
+        // failed install is not recorded.
+        assert_eq!(
+            state
+                .packages
+                .iter()
+                .map(|p| p.package.as_str())
+                .collect::<Vec<_>>(),
+            vec!["exa", "github.com/ahmetb/kubectx/cmd/kubectx"]
+        );
+    }
+
+    fn cargo_install(yaml: &str) -> Operation {
+        Operation::install_command(config::Command::Cargo(serde_yaml::from_str(yaml).unwrap()))
+    }
+
+    fn create_file(yaml: &str) -> Operation {
+        Operation::create_file(serde_yaml::from_str(yaml).unwrap(), Change::Create, None)
+    }
+
+    #[test]
+    fn resolve_operation_dependencies() {
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        let mut chain = OperationChain::new();
+        chain.add(Operation::create_symbolic_link(
+            serde_yaml::from_str(
+                r"
+description: link to target.
+original_env_base: HOME
+original_relative_path: x/target
+link_env_base: HOME
+link_relative_path: link
+",
+            )
+            .unwrap(),
+            Change::Create,
+        ));
+        chain.add(cargo_install("{ package: exa, depends_on: [target] }"));
+        chain.add(create_file(
+            r"
+id: target
+description: target file.
+env_base: HOME
+relative_path: x/target
+content_from: target
+mode: 0644
+",
+        ));
+        chain.add(Operation::create_intermediate_directory(
+            home.join("x"),
+            FilePermission::UnixMode(0o755),
+        ));
+
+        chain.resolve_dependencies().unwrap();
+        assert_eq!(
+            chain
+                .operations()
+                .iter()
+                .map(|ops| (ops.kind().description(), ops.dependencies().to_vec()))
+                .collect::<Vec<_>>(),
+            vec![
+                (home.join("x").display().to_string(), vec![]),
+                ("target file.".to_owned(), vec![0]),
+                ("link to target.".to_owned(), vec![1]),
+                ("exa".to_owned(), vec![1]),
+            ]
+        );
+
+        let mut chain = OperationChain::new();
+        chain.add(cargo_install("{ package: exa, depends_on: [target] }"));
+        chain.add(create_file(
+            r"
+id: target
+description: target file.
+env_base: HOME
+relative_path: target
+content_from: target
+mode: 0644
+depends_on: [exa]
+",
+        ));
+        match chain.resolve_dependencies().unwrap_err().kind() {
+            crate::error::ErrorKind::DependencyCycle { entries } => {
+                assert_eq!(entries, &["exa", "target file.", "exa"])
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+
+        // dependents of skipped entries are skipped transitively.
+        let mut chain = OperationChain::new();
+        chain.skip(
+            "starship config.",
+            Some("starship-config"),
+            SkipReason::Condition {
+                unmet: "os is linux, not mac".to_owned(),
+            },
+        );
+        chain.add(cargo_install(
+            "{ package: starship, depends_on: [starship-config] }",
+        ));
+        chain.add(cargo_install("{ package: exa, depends_on: [starship] }"));
+        chain.add(cargo_install("{ package: bat, depends_on: [up-to-date] }"));
+        chain.resolve_dependencies().unwrap();
+        assert_eq!(
+            chain
+                .operations()
+                .iter()
+                .map(|ops| ops.kind().description())
+                .collect::<Vec<_>>(),
+            ["bat"]
+        );
+        assert_eq!(
+            chain.skipped()[1..],
+            [
+                (
+                    "starship".to_owned(),
+                    SkipReason::DependencySkipped {
+                        dependency: "starship-config".to_owned()
+                    }
+                ),
+                (
+                    "exa".to_owned(),
+                    SkipReason::DependencySkipped {
+                        dependency: "starship".to_owned()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn skip_dependents_of_failed_operation() {
+        let config = Config::load_from_dir(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config_example"),
+            Os::Linux,
+        )
+        .await
+        .unwrap();
+        let mut sys = FakeSystem::new()
+            .with_directory("/home", FilePermission::UnixMode(0o755))
+            .with_command_output(&["cargo", "install", "exa"], success(""));
+        let mut chain = OperationChain::new();
+        chain.add(cargo_install(
+            "{ package: starship, depends_on: [missing] }",
+        ));
+        chain.add(cargo_install("{ package: exa }"));
+        // content_from does not exist, so creating file fails.
+        chain.add(create_file(
+            r"
+id: missing
+description: file without source.
+env_base: HOME
+relative_path: missing
+content_from: no/such/file
+mode: 0644
+",
+        ));
+        chain.resolve_dependencies().unwrap();
+
+        apply(ApplyParam {
+            system: &mut sys,
+            config: &config,
+            operation_chain: &mut chain,
+            backup_store: &mut BackupStore::new("/state"),
+            state: &mut State::default(),
+            dry_run: false,
+            prune: false,
+            jobs: 1,
+        })
+        .await
+        .unwrap();
+
+        let results = chain
+            .operations()
+            .iter()
+            .map(|ops| {
+                (
+                    ops.kind().description(),
+                    ops.result()
+                        .unwrap()
+                        .as_ref()
+                        .err()
+                        .map(|err| err.to_string()),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(results[0].0, "exa");
+        assert_eq!(results[0].1, None);
+        assert_eq!(results[1].0, "file without source.");
+        assert!(results[1].1.is_some());
+        assert_eq!(results[2].0, "starship");
+        assert!(results[2]
+            .1
+            .as_deref()
+            .unwrap()
+            .starts_with("skipped because file without source. failed"));
+        assert!(!sys.command_lines().contains(&vec![
+            "cargo".into(),
+            "install".into(),
+            "starship".into()
+        ]));
+    }
+}
@@ -1,17 +1,55 @@
-use crate::{config, prelude::Result};
+use tracing::debug;
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+};
+
+use crate::{
+    config,
+    error::ErrorKind,
+    operation::FileDiff,
+    prelude::{Error, Result},
+    state::ManagedPackage,
+    system::FilePermission,
+};
 
 #[derive(Debug)]
 pub struct OperationChain {
     operations: Vec<Operation>,
+    /// Entries in config not planned. Pairs of entry description and reason.
+    skipped: Vec<(String, SkipReason)>,
+    /// Ids of skipped entries, whose dependents are skipped as well.
+    skipped_ids: HashSet<String>,
 }
 
 impl OperationChain {
     pub(super) fn new() -> Self {
         Self {
             operations: Vec::new(),
+            skipped: Vec::new(),
+            skipped_ids: HashSet::new(),
+        }
+    }
+
+    /// Record entry not planned. id is given when entry can be referenced by `depends_on`.
+    pub(super) fn skip(&mut self, entry: impl Into<String>, id: Option<&str>, reason: SkipReason) {
+        self.skipped.push((entry.into(), reason));
+        if let Some(id) = id {
+            self.skipped_ids.insert(id.to_owned());
         }
     }
 
+    /// Whether entry of given id is not planned.
+    pub(super) fn is_skipped(&self, id: &str) -> bool {
+        self.skipped_ids.contains(id)
+    }
+
+    pub(super) fn skipped(&self) -> &[(String, SkipReason)] {
+        self.skipped.as_slice()
+    }
+
     pub(super) fn add(&mut self, ops: Operation) {
         self.operations.push(ops)
     }
@@ -27,12 +65,166 @@ impl OperationChain {
     pub(super) fn operations_mut(&mut self) -> &mut [Operation] {
         self.operations.as_mut()
     }
+
+    /// Resolve dependencies between operations and sort them so that every operation
+    /// comes after operations it depends on. Planned order is kept otherwise.
+    ///
+    /// Besides `depends_on` in config, entries depend on operations creating their parent
+    /// directories, and symlinks on operation creating their original.
+    /// Operations depending on skipped entries are skipped as well.
+    pub(super) fn resolve_dependencies(&mut self) -> Result<()> {
+        self.skip_dependents();
+
+        let mut by_id = HashMap::new();
+        let mut by_path = HashMap::new();
+        for (index, ops) in self.operations.iter().enumerate() {
+            if let Some(id) = ops.kind.id() {
+                by_id.insert(id.to_owned(), index);
+            }
+            if let Some(path) = ops.kind.created_path() {
+                by_path.insert(path, index);
+            }
+        }
+
+        let dependencies = self
+            .operations
+            .iter()
+            .enumerate()
+            .map(|(index, ops)| {
+                let explicit = ops.kind.depends_on().iter().filter_map(|id| {
+                    let dependency = by_id.get(id.as_str());
+                    if dependency.is_none() {
+                        debug!(
+                            "{} depends on {} which has nothing to apply",
+                            ops.kind.description(),
+                            id
+                        );
+                    }
+                    dependency
+                });
+                let implicit = ops
+                    .kind
+                    .required_paths()
+                    .into_iter()
+                    .filter_map(|path| by_path.get(&path));
+                explicit
+                    .chain(implicit)
+                    .copied()
+                    .filter(|dependency| *dependency != index)
+                    .collect::<BTreeSet<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Kahn's algorithm taking smallest planned index first.
+        let mut remaining = dependencies.iter().map(BTreeSet::len).collect::<Vec<_>>();
+        let mut dependents = vec![Vec::new(); dependencies.len()];
+        for (index, deps) in dependencies.iter().enumerate() {
+            for dependency in deps {
+                dependents[*dependency].push(index);
+            }
+        }
+        let mut ready = (0..remaining.len())
+            .filter(|index| remaining[*index] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::with_capacity(remaining.len());
+        while let Some(index) = ready.iter().next().copied() {
+            ready.remove(&index);
+            order.push(index);
+            for dependent in &dependents[index] {
+                remaining[*dependent] -= 1;
+                if remaining[*dependent] == 0 {
+                    ready.insert(*dependent);
+                }
+            }
+        }
+
+        if order.len() < self.operations.len() {
+            // operations left unordered are on a cycle or depend on one.
+            let mut visited = HashSet::new();
+            let cycle = (0..self.operations.len())
+                .filter(|index| remaining[*index] > 0)
+                .find_map(|index| {
+                    config::find_cycle(
+                        &|index: usize| dependencies[index].iter().copied(),
+                        index,
+                        &mut Vec::new(),
+                        &mut visited,
+                    )
+                })
+                .unwrap_or_default();
+            return Err(Error::from(ErrorKind::DependencyCycle {
+                entries: cycle
+                    .into_iter()
+                    .map(|index| self.operations[index].kind.description())
+                    .collect(),
+            }));
+        }
+
+        let mut new_index = vec![0; order.len()];
+        for (new, old) in order.iter().enumerate() {
+            new_index[*old] = new;
+        }
+        let mut operations = self.operations.drain(..).map(Some).collect::<Vec<_>>();
+        self.operations = order
+            .iter()
+            .map(|old| {
+                let mut ops = operations[*old]
+                    .take()
+                    .expect("each operation is ordered once");
+                ops.dependencies = dependencies[*old].iter().map(|d| new_index[*d]).collect();
+                ops.dependencies.sort_unstable();
+                ops
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Remove operations whose `depends_on` references skipped entry, recording them as skipped.
+    /// Repeated until no dependent is left, since removed operation may be depended on in turn.
+    fn skip_dependents(&mut self) {
+        while let Some((index, dependency)) =
+            self.operations.iter().enumerate().find_map(|(index, ops)| {
+                ops.kind
+                    .depends_on()
+                    .iter()
+                    .find(|id| self.skipped_ids.contains(id.as_str()))
+                    .map(|id| (index, id.clone()))
+            })
+        {
+            let ops = self.operations.remove(index);
+            let entry = match (&ops.kind, ops.kind.id()) {
+                (OperationKind::Command(_), Some(id)) => id.to_owned(),
+                _ => ops.kind.description(),
+            };
+            debug!("{} skipped since {} is skipped", entry, dependency);
+            self.skip(
+                entry,
+                ops.kind.id(),
+                SkipReason::DependencySkipped { dependency },
+            );
+        }
+    }
+
+    /// Number of operations removing entries dropped from config.
+    pub fn removal_count(&self) -> usize {
+        self.operations
+            .iter()
+            .filter(|ops| ops.kind().is_removal())
+            .count()
+    }
 }
 
 #[derive(Debug)]
 pub struct Operation<T = ()> {
     kind: OperationKind,
     result: Option<Result<T>>,
+    /// Why operation was not run by apply. Result is None then.
+    skipped: Option<SkipReason>,
+    /// Output of commands run by operation, captured instead of streamed.
+    output: Option<String>,
+    /// Indices of operations in chain which must succeed before this operation.
+    dependencies: Vec<usize>,
 }
 
 impl<T> Operation<T> {
@@ -45,16 +237,61 @@ impl<T> Operation<T> {
     pub(super) fn set_result(&mut self, result: Result<T>) {
         self.result = Some(result);
     }
+    pub(super) fn skipped(&self) -> Option<&SkipReason> {
+        self.skipped.as_ref()
+    }
+    pub(super) fn set_skipped(&mut self, reason: SkipReason) {
+        self.skipped = Some(reason);
+    }
+    pub(super) fn output(&self) -> Option<&str> {
+        self.output.as_deref()
+    }
+    pub(super) fn set_output(&mut self, output: String) {
+        self.output = Some(output);
+    }
+    pub(super) fn dependencies(&self) -> &[usize] {
+        self.dependencies.as_slice()
+    }
 
-    pub(super) fn create_file(entry: config::FileEntry) -> Self {
+    pub(super) fn create_file(
+        entry: config::FileEntry,
+        change: Change,
+        diff: Option<FileDiff>,
+    ) -> Self {
         Operation::with(OperationKind::Filesystem(FilesystemOperation::CreateFile {
             entry,
+            change,
+            diff,
+        }))
+    }
+
+    pub(super) fn create_symbolic_link(entry: config::SymlinkEntry, change: Change) -> Self {
+        Operation::with(OperationKind::Filesystem(
+            FilesystemOperation::CreateSymbolicLink { entry, change },
+        ))
+    }
+
+    pub(super) fn create_directory(entry: config::DirectoryEntry, change: Change) -> Self {
+        Operation::with(OperationKind::Filesystem(
+            FilesystemOperation::CreateDirectory { entry, change },
+        ))
+    }
+
+    pub(super) fn create_intermediate_directory(path: PathBuf, permission: FilePermission) -> Self {
+        Operation::with(OperationKind::Filesystem(
+            FilesystemOperation::CreateIntermediateDirectory { path, permission },
+        ))
+    }
+
+    pub(super) fn remove_file(path: PathBuf) -> Self {
+        Operation::with(OperationKind::Filesystem(FilesystemOperation::RemoveFile {
+            path,
         }))
     }
 
-    pub(super) fn create_symbolic_link(entry: config::SymlinkEntry) -> Self {
+    pub(super) fn remove_symbolic_link(path: PathBuf) -> Self {
         Operation::with(OperationKind::Filesystem(
-            FilesystemOperation::CreateSymbolicLink { entry },
+            FilesystemOperation::RemoveSymbolicLink { path },
         ))
     }
 
@@ -62,24 +299,286 @@ impl<T> Operation<T> {
         Operation::with(OperationKind::Command(CommandOperation::Install { cmd }))
     }
 
+    /// Reinstall command installed from other source than configured.
+    pub(super) fn reinstall_command(
+        cmd: config::Command,
+        installed: String,
+        configured: String,
+    ) -> Self {
+        Operation::with(OperationKind::Command(CommandOperation::Reinstall {
+            cmd,
+            installed,
+            configured,
+        }))
+    }
+
+    /// Reinstall command whose installed version does not satisfy requirement.
+    pub(super) fn change_command_version(
+        cmd: config::Command,
+        installed: semver::Version,
+        requirement: config::VersionRequirement,
+    ) -> Self {
+        let ops = if requirement.is_upgrade_from(&installed) {
+            CommandOperation::Upgrade {
+                cmd,
+                installed,
+                requirement,
+            }
+        } else {
+            CommandOperation::Downgrade {
+                cmd,
+                installed,
+                requirement,
+            }
+        };
+        Operation::with(OperationKind::Command(ops))
+    }
+
+    pub(super) fn uninstall_command(package: ManagedPackage) -> Self {
+        Operation::with(OperationKind::Command(CommandOperation::Uninstall {
+            package,
+        }))
+    }
+
     fn with(kind: OperationKind) -> Self {
-        Self { kind, result: None }
+        Self {
+            kind,
+            result: None,
+            skipped: None,
+            output: None,
+            dependencies: Vec::new(),
+        }
     }
 }
 
+// operations are planned once per entry, so size difference does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum OperationKind {
     Filesystem(FilesystemOperation),
     Command(CommandOperation),
 }
 
+impl OperationKind {
+    /// Whether operation removes entry dropped from config. Removals run only when pruning.
+    pub(super) fn is_removal(&self) -> bool {
+        matches!(
+            self,
+            OperationKind::Filesystem(FilesystemOperation::RemoveFile { .. })
+                | OperationKind::Filesystem(FilesystemOperation::RemoveSymbolicLink { .. })
+                | OperationKind::Command(CommandOperation::Uninstall { .. })
+        )
+    }
+
+    /// Id of config entry operation is planned from.
+    fn id(&self) -> Option<&str> {
+        match self {
+            OperationKind::Filesystem(ops_fs) => ops_fs.entry_base().and_then(|b| b.id.as_deref()),
+            OperationKind::Command(ops_cmd) => ops_cmd.cmd().map(config::Command::id),
+        }
+    }
+
+    /// Ids given by `depends_on` of config entry.
+    fn depends_on(&self) -> &[String] {
+        match self {
+            OperationKind::Filesystem(ops_fs) => ops_fs
+                .entry_base()
+                .map(|b| b.depends_on.as_slice())
+                .unwrap_or_default(),
+            OperationKind::Command(ops_cmd) => ops_cmd
+                .cmd()
+                .map(config::Command::depends_on)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Path which exists after operation is applied.
+    fn created_path(&self) -> Option<PathBuf> {
+        match self {
+            OperationKind::Filesystem(ops_fs) => match ops_fs {
+                FilesystemOperation::CreateFile { entry, .. } => entry.dest_path().ok(),
+                FilesystemOperation::CreateSymbolicLink { entry, .. } => entry.link_path().ok(),
+                FilesystemOperation::CreateDirectory { entry, .. } => entry.path().ok(),
+                FilesystemOperation::CreateIntermediateDirectory { path, .. } => Some(path.clone()),
+                FilesystemOperation::RemoveFile { .. }
+                | FilesystemOperation::RemoveSymbolicLink { .. } => None,
+            },
+            OperationKind::Command(_) => None,
+        }
+    }
+
+    /// Paths which must exist before operation is applied. Ancestors of created path and symlink original.
+    fn required_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .created_path()
+            .map(|path| path.ancestors().skip(1).map(PathBuf::from).collect())
+            .unwrap_or_default();
+        if let OperationKind::Filesystem(FilesystemOperation::CreateSymbolicLink {
+            entry, ..
+        }) = self
+        {
+            paths.extend(entry.original_path().ok());
+        }
+        paths
+    }
+
+    /// Short description used in dependency errors.
+    pub(super) fn description(&self) -> String {
+        match self {
+            OperationKind::Filesystem(ops_fs) => match ops_fs.entry_base() {
+                Some(base) => base.description.clone(),
+                None => self
+                    .created_path()
+                    .or_else(|| match ops_fs {
+                        FilesystemOperation::RemoveFile { path }
+                        | FilesystemOperation::RemoveSymbolicLink { path } => Some(path.clone()),
+                        _ => None,
+                    })
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default(),
+            },
+            OperationKind::Command(ops_cmd) => match ops_cmd {
+                CommandOperation::Uninstall { package } => package.package.clone(),
+                _ => ops_cmd
+                    .cmd()
+                    .map(|c| c.package().to_owned())
+                    .unwrap_or_default(),
+            },
+        }
+    }
+}
+
+impl FilesystemOperation {
+    fn entry_base(&self) -> Option<&config::FilesystemEntryBase> {
+        match self {
+            FilesystemOperation::CreateFile { entry, .. } => Some(&entry.base),
+            FilesystemOperation::CreateSymbolicLink { entry, .. } => Some(&entry.base),
+            FilesystemOperation::CreateDirectory { entry, .. } => Some(&entry.base),
+            _ => None,
+        }
+    }
+}
+
+impl CommandOperation {
+    fn cmd(&self) -> Option<&config::Command> {
+        match self {
+            CommandOperation::Install { cmd }
+            | CommandOperation::Reinstall { cmd, .. }
+            | CommandOperation::Upgrade { cmd, .. }
+            | CommandOperation::Downgrade { cmd, .. } => Some(cmd),
+            CommandOperation::Uninstall { .. } => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum FilesystemOperation {
-    CreateFile { entry: config::FileEntry },
-    CreateSymbolicLink { entry: config::SymlinkEntry },
+    CreateFile {
+        entry: config::FileEntry,
+        change: Change,
+        /// Difference from existing file on update.
+        diff: Option<FileDiff>,
+    },
+    CreateSymbolicLink {
+        entry: config::SymlinkEntry,
+        change: Change,
+    },
+    CreateDirectory {
+        entry: config::DirectoryEntry,
+        change: Change,
+    },
+    /// Missing parent directory of file or symlink.
+    CreateIntermediateDirectory {
+        path: PathBuf,
+        permission: FilePermission,
+    },
+    /// File created by localenv whose entry was dropped from config.
+    RemoveFile { path: PathBuf },
+    /// Symlink created by localenv whose entry was dropped from config.
+    RemoveSymbolicLink { path: PathBuf },
+}
+
+/// Why entry in config is not planned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    /// Entry condition is not met.
+    Condition { unmet: String },
+    /// Entry has tag excluded by active profile.
+    ExcludedTag { profile: String, tag: String },
+    /// Entry has no tag included by active profile.
+    NotIncluded { profile: String },
+    /// Entry depends on skipped entry.
+    DependencySkipped { dependency: String },
+    /// Installer of entry could not be run. e.g. not found in $PATH.
+    InstallerUnavailable { installer: String, reason: String },
+    /// Removal of entry dropped from config, run only with `--prune`.
+    PruneDisabled,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::Condition { unmet } => write!(f, "condition not met: {}", unmet),
+            SkipReason::ExcludedTag { profile, tag } => {
+                write!(f, "tag {} excluded by profile {}", tag, profile)
+            }
+            SkipReason::NotIncluded { profile } => {
+                write!(f, "no tag included by profile {}", profile)
+            }
+            SkipReason::DependencySkipped { dependency } => {
+                write!(f, "depends on skipped entry {}", dependency)
+            }
+            SkipReason::InstallerUnavailable { installer, reason } => {
+                write!(f, "{} is not available: {}", installer, reason)
+            }
+            SkipReason::PruneDisabled => f.write_str("removal runs only with --prune"),
+        }
+    }
+}
+
+/// Whether filesystem operation creates new entry or modifies existing one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Create,
+    Update,
+}
+
+impl Change {
+    pub(super) fn label(self) -> &'static str {
+        match self {
+            Change::Create => "Create",
+            Change::Update => "Update",
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum CommandOperation {
-    Install { cmd: config::Command },
+    Install {
+        cmd: config::Command,
+    },
+    /// Installed from other source than configured.
+    Reinstall {
+        cmd: config::Command,
+        /// Source installed from.
+        installed: String,
+        /// Source in config.
+        configured: String,
+    },
+    /// Installed version is lower than required.
+    Upgrade {
+        cmd: config::Command,
+        installed: semver::Version,
+        requirement: config::VersionRequirement,
+    },
+    /// Installed version is higher than required.
+    Downgrade {
+        cmd: config::Command,
+        installed: semver::Version,
+        requirement: config::VersionRequirement,
+    },
+    /// Package installed by localenv whose entry was dropped from config.
+    Uninstall {
+        package: ManagedPackage,
+    },
 }
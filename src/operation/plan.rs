@@ -2,83 +2,474 @@
 use tracing::{debug, error, info, trace, warn};
 
 use crate::{
-    config::{self, Commands, Config, Filesystem, FilesystemEntry},
-    operation::{installer, Operation, OperationChain},
+    config::{
+        self, CommandEntry, Condition, Config, DirectoryEntry, FileEntry, Filesystem,
+        FilesystemEntry, SymlinkEntry,
+    },
+    operation::{
+        condition, installer, template, Change, FileDiff, Operation, OperationChain, SkipReason,
+    },
     prelude::*,
-    system,
+    state::{self, ManagedKind, State},
+    system::{self, FilePermission, FileType},
 };
-use nom::lib::std::collections::HashMap;
 
-pub async fn plan<Api>(sys: Api, config: &Config) -> Result<OperationChain>
+use std::{collections::HashSet, path::PathBuf};
+
+/// Plan operations to reach the state described by config.
+/// Entries recorded in state but dropped from config are planned as removals.
+/// Operations are sorted so that each comes after operations it depends on.
+pub async fn plan<Api>(mut sys: Api, config: &Config, state: &State) -> Result<OperationChain>
 where
     Api: system::Api,
 {
     let mut chain = OperationChain::new();
 
-    plan_filesystem(&config.spec.filesystem, sys.os(), &mut chain).await?;
-    plan_commands(&config.spec.commands, &mut chain).await?;
+    plan_filesystem(&sys, config, &mut chain).await?;
+    plan_removals(&sys, &config.spec.filesystem, state, &mut chain)?;
+    plan_commands(&mut sys, config, state, &mut chain).await?;
+    chain.resolve_dependencies()?;
 
     Ok(chain)
 }
 
-async fn plan_filesystem(
+async fn plan_filesystem<Api>(sys: &Api, config: &Config, chain: &mut OperationChain) -> Result<()>
+where
+    Api: system::Api,
+{
+    let fs = &config.spec.filesystem;
+    let dir_permission = fs.default_directory_permission()?;
+    // directories which will exist once preceding operations are applied.
+    let mut planned_dirs = HashSet::new();
+
+    for entry in fs.entries.iter() {
+        trace!("{:?}", entry);
+
+        if let Some(reason) = skip_reason(sys, config, entry.condition(), entry.tags())? {
+            debug!("entry {} skipped: {}", entry.description(), reason);
+            chain.skip(entry.description(), entry.id(), reason);
+            continue;
+        }
+
+        let ops = match entry {
+            FilesystemEntry::File(file) => match file_change(sys, config, file)? {
+                Some((change, diff)) => {
+                    chain.extend(plan_intermediate_directories(
+                        sys,
+                        file.dest_path()?,
+                        dir_permission,
+                        &mut planned_dirs,
+                    )?);
+                    Operation::create_file(file.clone(), change, diff)
+                }
+                None => {
+                    debug!("entry {} unchanged", entry.description());
+                    continue;
+                }
+            },
+            FilesystemEntry::SymbolicLink(sym) => match symbolic_link_change(sys, sym)? {
+                Some(change) => {
+                    chain.extend(plan_intermediate_directories(
+                        sys,
+                        sym.link_path()?,
+                        dir_permission,
+                        &mut planned_dirs,
+                    )?);
+                    Operation::create_symbolic_link(sym.clone(), change)
+                }
+                None => {
+                    debug!("entry {} unchanged", entry.description());
+                    continue;
+                }
+            },
+            FilesystemEntry::Directory(dir) => {
+                planned_dirs.insert(dir.path()?);
+                match directory_change(sys, dir)? {
+                    Some(change) => Operation::create_directory(dir.clone(), change),
+                    None => {
+                        debug!("entry {} unchanged", entry.description());
+                        continue;
+                    }
+                }
+            }
+        };
+        chain.add(ops);
+    }
+
+    Ok(())
+}
+
+/// Compare destination content and mode with file entry. None if already up to date.
+fn file_change<Api>(
+    sys: &Api,
+    config: &Config,
+    entry: &FileEntry,
+) -> Result<Option<(Change, Option<FileDiff>)>>
+where
+    Api: system::Api,
+{
+    let dest = entry.dest_path()?;
+    match sys.file_type(&dest)? {
+        None => {
+            // render now so that template errors are reported by plan rather than apply.
+            if entry.template {
+                template::file_content(sys, config, entry)?;
+            }
+            Ok(Some((Change::Create, None)))
+        }
+        Some(FileType::File) => {
+            let src = entry.src_path(config.root_dir.as_path());
+            let desired = template::file_content(sys, config, entry)?;
+            let desired_permission = entry.permission()?;
+            let current = sys.read_file(&dest)?;
+            let current_permission = sys.permission(&dest)?;
+            if state::content_hash(&desired) == state::content_hash(&current)
+                && current_permission == desired_permission
+            {
+                Ok(None)
+            } else {
+                let diff = FileDiff::new(
+                    &dest,
+                    &current,
+                    current_permission,
+                    &src,
+                    &desired,
+                    desired_permission,
+                );
+                Ok(Some((Change::Update, Some(diff))))
+            }
+        }
+        Some(_) => Ok(Some((Change::Update, None))),
+    }
+}
+
+/// Compare link target with symlink entry. None if already up to date.
+fn symbolic_link_change<Api>(sys: &Api, entry: &SymlinkEntry) -> Result<Option<Change>>
+where
+    Api: system::Api,
+{
+    let link = entry.link_path()?;
+    match sys.file_type(&link)? {
+        None => Ok(Some(Change::Create)),
+        Some(FileType::SymbolicLink) if sys.read_link(&link)? == entry.original_path()? => Ok(None),
+        Some(_) => Ok(Some(Change::Update)),
+    }
+}
+
+/// Compare existing directory mode with directory entry. None if already up to date.
+fn directory_change<Api>(sys: &Api, entry: &DirectoryEntry) -> Result<Option<Change>>
+where
+    Api: system::Api,
+{
+    let path = entry.path()?;
+    match sys.file_type(&path)? {
+        None => Ok(Some(Change::Create)),
+        Some(FileType::Directory) if sys.permission(&path)? == entry.permission()? => Ok(None),
+        Some(_) => Ok(Some(Change::Update)),
+    }
+}
+
+/// Plan removal of entries created by localenv which are no longer listed in config.
+fn plan_removals<Api>(
+    sys: &Api,
     fs: &Filesystem,
-    sys_os: system::Os,
+    state: &State,
     chain: &mut OperationChain,
-) -> Result<()> {
-    let ops = fs
+) -> Result<()>
+where
+    Api: system::Api,
+{
+    let listed = fs
         .entries
         .iter()
-        .flat_map(|entry| {
-            trace!("{:?}", entry);
-
-            // check condition
-            if let Some(cond) = entry.condition() {
-                // check os
-                if let Some(os) = cond.os {
-                    if os != sys_os {
-                        debug!(
-                            "entry {} does not match os condition. os: {}",
-                            entry.description(),
-                            os
-                        );
-                        return None;
-                    }
-                    debug!("entry {} match os condition", entry.description());
-                }
+        .map(FilesystemEntry::path)
+        .collect::<Result<HashSet<_>>>()?;
+
+    for entry in state
+        .filesystem
+        .iter()
+        .filter(|entry| entry.created && !listed.contains(&entry.path))
+    {
+        match (&entry.kind, sys.file_type(&entry.path)?) {
+            (ManagedKind::File { .. }, Some(FileType::File)) => {
+                chain.add(Operation::remove_file(entry.path.clone()))
             }
+            (ManagedKind::SymbolicLink { .. }, Some(FileType::SymbolicLink)) => {
+                chain.add(Operation::remove_symbolic_link(entry.path.clone()))
+            }
+            (ManagedKind::Directory { .. }, _) => {
+                debug!("keep directory {}", entry.path.display())
+            }
+            (_, current) => debug!(
+                "{} changed outside localenv ({:?}), skip removal",
+                entry.path.display(),
+                current
+            ),
+        }
+    }
 
-            let ops = match entry {
-                FilesystemEntry::File(file) => Operation::create_file(file.clone()),
-                FilesystemEntry::SymbolicLink(sym) => Operation::create_symbolic_link(sym.clone()),
-                _ => unimplemented!(),
-            };
-            Some(ops)
-        })
-        .collect();
+    Ok(())
+}
 
-    chain.extend(ops);
+/// Plan creation of missing ancestor directories of given path, outermost first.
+fn plan_intermediate_directories<Api>(
+    sys: &Api,
+    path: PathBuf,
+    permission: FilePermission,
+    planned_dirs: &mut HashSet<PathBuf>,
+) -> Result<Vec<Operation>>
+where
+    Api: system::Api,
+{
+    let mut missing = Vec::new();
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if planned_dirs.contains(d) || sys.file_type(d)?.is_some() {
+            break;
+        }
+        missing.push(d.to_path_buf());
+        dir = d.parent();
+    }
 
-    Ok(())
+    Ok(missing
+        .into_iter()
+        .rev()
+        .map(|d| {
+            debug!("{} does not exist", d.display());
+            planned_dirs.insert(d.clone());
+            Operation::create_intermediate_directory(d, permission)
+        })
+        .collect())
 }
 
-async fn plan_commands(commands: &Commands, chain: &mut OperationChain) -> Result<()> {
+/// Plan installation of missing commands and uninstallation of packages
+/// installed by localenv which are no longer listed in config.
+async fn plan_commands<Api>(
+    sys: &mut Api,
+    config: &Config,
+    state: &State,
+    chain: &mut OperationChain,
+) -> Result<()>
+where
+    Api: system::Api,
+{
     use config::Command;
 
-    if let Some(ref cargo_commands) = commands.cargo {
-        let mut cargo = installer::Cargo::new()?;
-        let installed_packages = cargo.list_installed_packages().await?;
+    let commands = &config.spec.commands;
+
+    // commands skipped by profile are still in config, so they are not dropped.
+    let dropped_packages = state
+        .packages
+        .iter()
+        .filter(|p| {
+            p.installer == installer::CARGO
+                && p.created
+                && !commands
+                    .cargo
+                    .iter()
+                    .flatten()
+                    .any(|c| p.is(installer::CARGO, c.package(), c.root.as_deref()))
+        })
+        .collect::<Vec<_>>();
+    let cargo_commands = select_commands(sys, config, chain, commands.cargo.as_deref())?;
+    let brew_commands = select_commands(sys, config, chain, commands.brew.as_deref())?;
+    let go_commands = select_commands(sys, config, chain, commands.go.as_deref())?;
+
+    if !cargo_commands.is_empty() || !dropped_packages.is_empty() {
+        let mut cargo = installer::Cargo::new(&mut *sys);
+        let installed_packages = cargo
+            .list_installed_packages_by_root(
+                cargo_commands
+                    .iter()
+                    .map(|c| c.root.as_deref())
+                    .chain(dropped_packages.iter().map(|p| p.root.as_deref())),
+            )
+            .await?;
         trace!("cargo installed packages: {:#?}", installed_packages);
 
-        cargo_commands.iter().for_each(|c| {
-            let installed = installed_packages.iter().any(|p| p.bin() == c.bin());
-            if !installed {
-                chain.add(Operation::install_command(Command::Cargo(c.clone())));
+        for c in cargo_commands.iter() {
+            let requirement = c.version_requirement()?;
+            let installed = installed_packages.find(c);
+            if let (Some(p), Some(bin)) = (installed, c.configured_bin()) {
+                if !p.provides(bin) {
+                    warn!(
+                        "{} {} does not provide configured bin {}. provided bins: {}",
+                        p.name(),
+                        p.version(),
+                        bin,
+                        p.bins().join(", ")
+                    );
+                }
+            }
+            match installed {
+                None => chain.add(Operation::install_command(Command::Cargo(c.clone()))),
+                Some(p) if !p.satisfies(c) => chain.add(Operation::reinstall_command(
+                    Command::Cargo(c.clone()),
+                    p.description(),
+                    c.description(),
+                )),
+                Some(p) => match requirement {
+                    Some(requirement) if !requirement.matches(p.version()) => {
+                        chain.add(Operation::change_command_version(
+                            Command::Cargo(c.clone()),
+                            p.version().clone(),
+                            requirement,
+                        ))
+                    }
+                    _ => debug!("{} {} already installed", c.bin(), p.version()),
+                },
+            }
+        }
+
+        for package in dropped_packages {
+            if installed_packages
+                .find_by_name(&package.package, package.root.as_deref())
+                .is_some()
+            {
+                chain.add(Operation::uninstall_command(package.clone()));
             } else {
-                debug!("{} already installed", c.bin());
+                debug!("{} already uninstalled", package.package);
             }
-        })
+        }
+    }
+
+    if !brew_commands.is_empty() {
+        let mut brew = installer::Brew::new(&mut *sys);
+        match brew.list_installed_formulae().await {
+            Ok(installed_formulae) => {
+                trace!("brew installed formulae: {:#?}", installed_formulae);
+
+                brew_commands.iter().for_each(|c| {
+                    let installed = installed_formulae.iter().any(|f| f.matches(c));
+                    if !installed {
+                        chain.add(Operation::install_command(Command::Brew(c.clone())));
+                    } else {
+                        debug!("{} already installed", c.package());
+                    }
+                })
+            }
+            Err(err) => skip_unavailable(chain, installer::BREW, &err, &brew_commands),
+        }
+    }
+
+    if !go_commands.is_empty() {
+        let mut go = installer::Go::new(&mut *sys, config.spec.lang.goroot());
+        match go.bin_dir().await {
+            Ok(bin_dir) => {
+                debug!("go bin dir: {}", bin_dir.display());
+
+                for c in go_commands.iter() {
+                    let binary = match go.installed_binary(&bin_dir, c).await? {
+                        None => {
+                            chain.add(Operation::install_command(Command::Go(c.clone())));
+                            continue;
+                        }
+                        Some(binary) => binary,
+                    };
+                    match (c.version_requirement(), binary.semver()) {
+                        (Some(requirement), Some(installed))
+                            if !requirement.matches(&installed) =>
+                        {
+                            chain.add(Operation::change_command_version(
+                                Command::Go(c.clone()),
+                                installed,
+                                requirement,
+                            ))
+                        }
+                        (Some(requirement), None) => warn!(
+                            "version of installed {} is unknown, keep it instead of {}",
+                            c.bin(),
+                            requirement
+                        ),
+                        _ => debug!("{} already installed {:?}", c.bin(), binary.version()),
+                    }
+                }
+            }
+            Err(err) => skip_unavailable(chain, installer::GO, &err, &go_commands),
+        }
     }
 
     Ok(())
 }
+
+/// Skip every command of installer which could not be run, warning once.
+fn skip_unavailable<C: CommandEntry>(
+    chain: &mut OperationChain,
+    installer: &str,
+    err: &Error,
+    commands: &[C],
+) {
+    warn!("{} is not available, skip its commands: {}", installer, err);
+    for c in commands {
+        let id = c.base().id();
+        chain.skip(
+            id,
+            Some(id),
+            SkipReason::InstallerUnavailable {
+                installer: installer.to_owned(),
+                reason: err.to_string(),
+            },
+        );
+    }
+}
+
+/// Commands whose condition is met and which are selected by active profile.
+/// Skipped commands are recorded to chain.
+fn select_commands<Api, C>(
+    sys: &Api,
+    config: &Config,
+    chain: &mut OperationChain,
+    commands: Option<&[C]>,
+) -> Result<Vec<C>>
+where
+    Api: system::Api,
+    C: CommandEntry + Clone,
+{
+    let mut selected = Vec::new();
+    for c in commands.unwrap_or_default() {
+        let base = c.base();
+        let id = base.id();
+        match skip_reason(sys, config, base.condition(), base.tags())? {
+            Some(reason) => {
+                debug!("command {} skipped: {}", id, reason);
+                chain.skip(id, Some(id), reason);
+            }
+            None => selected.push(c.clone()),
+        }
+    }
+    Ok(selected)
+}
+
+/// Reason entry is skipped by its condition or active profile. None if entry is applied.
+fn skip_reason<Api>(
+    sys: &Api,
+    config: &Config,
+    condition: Option<&Condition>,
+    tags: &[String],
+) -> Result<Option<SkipReason>>
+where
+    Api: system::Api,
+{
+    if let Some(condition) = condition {
+        if let Some(unmet) = condition::unmet(sys, condition)? {
+            return Ok(Some(SkipReason::Condition { unmet }));
+        }
+    }
+
+    let (name, profile) = match config.active_profile() {
+        Some(profile) => profile,
+        None => return Ok(None),
+    };
+    Ok(if let Some(tag) = profile.excluded_tag(tags) {
+        Some(SkipReason::ExcludedTag {
+            profile: name.to_owned(),
+            tag: tag.to_owned(),
+        })
+    } else if !profile.includes(tags) {
+        Some(SkipReason::NotIncluded {
+            profile: name.to_owned(),
+        })
+    } else {
+        None
+    })
+}
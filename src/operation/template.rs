@@ -0,0 +1,139 @@
+use minijinja::{context, Environment, UndefinedBehavior};
+
+use std::{collections::BTreeMap, env, path::Path};
+
+use crate::{
+    config::{Config, FileEntry},
+    prelude::*,
+    system,
+};
+
+/// Read content of file entry, rendering it when entry is a template.
+pub(super) fn file_content<Api>(sys: &Api, cfg: &Config, entry: &FileEntry) -> Result<Vec<u8>>
+where
+    Api: system::Api,
+{
+    let src = entry.src_path(cfg.root_dir.as_path());
+    let content = sys.read_file(&src)?;
+    if entry.template {
+        render(sys, cfg, &src, content).map(String::into_bytes)
+    } else {
+        Ok(content)
+    }
+}
+
+/// Render template with env vars, os, hostname and vars of config.
+fn render<Api>(sys: &Api, cfg: &Config, path: &Path, content: Vec<u8>) -> Result<String>
+where
+    Api: system::Api,
+{
+    let source = String::from_utf8(content).map_err(|err| {
+        Error::from(ErrorKind::TemplateRenderFailed {
+            path: path.to_path_buf(),
+            line: None,
+            message: err.to_string(),
+        })
+    })?;
+    let name = path.display().to_string();
+
+    let mut engine = Environment::new();
+    engine.set_keep_trailing_newline(true);
+    // typo in variable name should not silently render empty string.
+    engine.set_undefined_behavior(UndefinedBehavior::Strict);
+
+    let ctx = context! {
+        env => env::vars().collect::<BTreeMap<_, _>>(),
+        os => sys.os().to_string(),
+        hostname => sys.hostname()?,
+        vars => &cfg.spec.vars,
+    };
+    engine
+        .template_from_named_str(&name, &source)
+        .and_then(|template| template.render(ctx))
+        .map_err(|err| {
+            Error::from(ErrorKind::TemplateRenderFailed {
+                path: path.to_path_buf(),
+                line: err.line(),
+                message: match err.detail() {
+                    Some(detail) => format!("{}: {}", err.kind(), detail),
+                    None => err.kind().to_string(),
+                },
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{fake::FakeSystem, FilePermission, Os};
+
+    fn config(vars: &str) -> Config {
+        Config {
+            spec: serde_yaml::from_str(&format!(
+                "localenv: '1'\nrequired_envs: []\nvars:\n{}\ncommands: {{}}\nfilesystem:\n  entries: []",
+                vars
+            ))
+            .unwrap(),
+            root_dir: "/".into(),
+            revision: String::new(),
+            profile: None,
+        }
+    }
+
+    #[test]
+    fn render_template() {
+        let sys = FakeSystem::new().with_os(Os::Mac).with_hostname("work");
+        let cfg = config("  editor: nvim\n  plugins: [git, fzf]");
+        let template = r"{% if os == 'mac' %}mac {{ hostname }}{% endif %}
+editor={{ vars.editor }} home={{ env.HOME }}
+{% for plugin in vars.plugins %}{{ plugin }};{% endfor %}
+";
+        let rendered =
+            render(&sys, &cfg, Path::new("zshrc"), template.as_bytes().to_vec()).unwrap();
+        assert_eq!(
+            rendered,
+            format!(
+                "mac work\neditor=nvim home={}\ngit;fzf;\n",
+                env::var("HOME").unwrap()
+            )
+        );
+
+        let err = render(
+            &sys,
+            &cfg,
+            Path::new("zshrc"),
+            b"ok\n{{ vars.no_such_var }}\n".to_vec(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::TemplateRenderFailed { path, line: Some(2), .. } if path == Path::new("zshrc")
+        ));
+    }
+
+    #[test]
+    fn read_template_through_system() {
+        let sys = FakeSystem::new().with_hostname("work").with_file(
+            "/config/zshrc",
+            "host={{ hostname }}\n",
+            FilePermission::UnixMode(0o644),
+        );
+        let cfg = config("  editor: nvim");
+        let entry = |template: bool| -> FileEntry {
+            serde_yaml::from_str(&format!(
+                "{{ description: zshrc, env_base: HOME, relative_path: .zshrc, content_from: /config/zshrc, mode: 0644, template: {} }}",
+                template
+            ))
+            .unwrap()
+        };
+
+        assert_eq!(
+            file_content(&sys, &cfg, &entry(true)).unwrap(),
+            b"host=work\n"
+        );
+        assert_eq!(
+            file_content(&sys, &cfg, &entry(false)).unwrap(),
+            b"host={{ hostname }}\n"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 /// Logging utilities.
+#[allow(unused_imports)]
 pub use tracing::{debug, error, info, trace, warn};
 
 /// A specialized Result type for this crate.
@@ -0,0 +1,330 @@
+use serde::{Deserialize, Serialize};
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    prelude::*,
+    system::{self, FilePermission, FileType},
+};
+
+const BACKUPS_DIR: &str = "backups";
+const MANIFEST_FILE: &str = "manifest.yaml";
+const BACKUP_DIR_PERMISSION: FilePermission = FilePermission::UnixMode(0o700);
+const BACKUP_FILE_PERMISSION: FilePermission = FilePermission::UnixMode(0o600);
+/// Mode of parent directories recreated on restore.
+const RESTORE_DIR_PERMISSION: FilePermission = FilePermission::UnixMode(0o755);
+
+/// Content of manifest file describing what a backup holds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub created_at: String,
+    pub entries: Vec<BackupEntry>,
+}
+
+/// Filesystem entry state captured before it was overwritten.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BackupEntry {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub kind: BackupKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackupKind {
+    File {
+        mode: String,
+        /// File name of the saved content in backup directory.
+        content: String,
+    },
+    SymbolicLink {
+        original: PathBuf,
+    },
+}
+
+/// Backup taken in one localenv run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub id: String,
+    pub manifest: Manifest,
+}
+
+/// Timestamped backup directories under `<state_dir>/backups`.
+pub struct BackupStore {
+    root: PathBuf,
+    /// Backup taken by this store, created on first save.
+    current: Option<(PathBuf, Manifest)>,
+}
+
+impl BackupStore {
+    pub fn new(state_dir: impl AsRef<Path>) -> Self {
+        Self {
+            root: state_dir.as_ref().join(BACKUPS_DIR),
+            current: None,
+        }
+    }
+
+    /// Directory of backup taken by this store, if any.
+    pub fn current_dir(&self) -> Option<&Path> {
+        self.current.as_ref().map(|(dir, _)| dir.as_path())
+    }
+
+    /// Save file or symlink at given path before it is overwritten or removed.
+    /// Nothing is saved if path does not exist.
+    pub fn save<Api>(&mut self, system: &mut Api, path: impl AsRef<Path>) -> Result<()>
+    where
+        Api: system::Api,
+    {
+        let path = path.as_ref();
+        let kind = match system.file_type(path)? {
+            None => return Ok(()),
+            Some(FileType::Directory) => {
+                debug!("{} is directory, skip backup", path.display());
+                return Ok(());
+            }
+            Some(FileType::SymbolicLink) => BackupKind::SymbolicLink {
+                original: system.read_link(path)?,
+            },
+            Some(FileType::File) => {
+                let content = system.read_file(path)?;
+                let mode = system.permission(path)?.to_string();
+                let (dir, manifest) = self.current_or_create(system)?;
+                let content_name = manifest.entries.len().to_string();
+                system.create_file(
+                    dir.join(&content_name),
+                    content.as_slice(),
+                    BACKUP_FILE_PERMISSION,
+                )?;
+                BackupKind::File {
+                    mode,
+                    content: content_name,
+                }
+            }
+        };
+
+        let (dir, manifest) = self.current_or_create(system)?;
+        manifest.entries.push(BackupEntry {
+            path: path.to_path_buf(),
+            kind,
+        });
+        let (dir, manifest) = (dir.clone(), manifest.clone());
+        write_manifest(system, &dir, &manifest)?;
+
+        info!("backed up {} to {}", path.display(), dir.display());
+
+        Ok(())
+    }
+
+    /// List backups, oldest first.
+    pub fn list<Api>(&self, system: &Api) -> Result<Vec<Backup>>
+    where
+        Api: system::Api,
+    {
+        if system.file_type(&self.root)?.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let mut ids = system
+            .read_dir(&self.root)?
+            .into_iter()
+            .filter_map(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .collect::<Vec<_>>();
+        ids.sort();
+
+        ids.into_iter()
+            .map(|id| {
+                let manifest = read_manifest(system, &self.root.join(&id))?;
+                Ok(Backup { id, manifest })
+            })
+            .collect()
+    }
+
+    /// Put entries of given backup back in place, newest change first.
+    /// Entries being overwritten are saved to a new backup so restore can be undone.
+    pub fn restore<Api>(&mut self, system: &mut Api, id: &str, dry_run: bool) -> Result<Backup>
+    where
+        Api: system::Api,
+    {
+        let dir = self.root.join(id);
+        if system.file_type(&dir)? != Some(FileType::Directory) {
+            return Err(Error::from(ErrorKind::BackupNotFound { id: id.to_owned() }));
+        }
+        let manifest = read_manifest(system, &dir)?;
+        if dry_run {
+            return Ok(Backup {
+                id: id.to_owned(),
+                manifest,
+            });
+        }
+
+        for entry in manifest.entries.iter().rev() {
+            self.save(system, &entry.path)?;
+
+            if let Some(parent) = entry.path.parent() {
+                if system.file_type(parent)?.is_none() {
+                    system.create_dir(parent, RESTORE_DIR_PERMISSION, true)?;
+                }
+            }
+
+            match &entry.kind {
+                BackupKind::File { mode, content } => {
+                    let permission = u32::from_str_radix(mode, 8)
+                        .map(FilePermission::UnixMode)
+                        .map_err(|_| ErrorKind::InvalidFilePermission { raw: mode.clone() })?;
+                    let content = system.read_file(dir.join(content))?;
+                    if system.file_type(&entry.path)? == Some(FileType::SymbolicLink) {
+                        // do not write through the link.
+                        system.remove_file(&entry.path)?;
+                    }
+                    system.create_file(&entry.path, content.as_slice(), permission)?;
+                }
+                BackupKind::SymbolicLink { original } => {
+                    system.create_symbolic_link(original, &entry.path)?;
+                }
+            }
+            info!("restored {}", entry.path.display());
+        }
+
+        Ok(Backup {
+            id: id.to_owned(),
+            manifest,
+        })
+    }
+
+    fn current_or_create<Api>(&mut self, system: &mut Api) -> Result<&mut (PathBuf, Manifest)>
+    where
+        Api: system::Api,
+    {
+        if self.current.is_none() {
+            let now = chrono::Local::now();
+            let base = now.format("%Y%m%d-%H%M%S").to_string();
+            let mut id = base.clone();
+            let mut seq = 0;
+            while system.file_type(self.root.join(&id))?.is_some() {
+                seq += 1;
+                id = format!("{}-{}", base, seq);
+            }
+
+            let dir = self.root.join(id);
+            system.create_dir(&dir, BACKUP_DIR_PERMISSION, true)?;
+            let manifest = Manifest {
+                created_at: now.to_rfc3339(),
+                entries: Vec::new(),
+            };
+            self.current = Some((dir, manifest));
+        }
+
+        Ok(self.current.as_mut().unwrap())
+    }
+}
+
+fn write_manifest<Api>(system: &mut Api, dir: &Path, manifest: &Manifest) -> Result<()>
+where
+    Api: system::Api,
+{
+    let yaml = serde_yaml::to_string(manifest)
+        .map_err(|err| ErrorKind::Internal(format!("serialize backup manifest: {}", err)))?;
+
+    system.create_file(
+        dir.join(MANIFEST_FILE),
+        yaml.as_bytes(),
+        BACKUP_FILE_PERMISSION,
+    )
+}
+
+fn read_manifest<Api>(system: &Api, dir: &Path) -> Result<Manifest>
+where
+    Api: system::Api,
+{
+    let path = dir.join(MANIFEST_FILE);
+    let content = system.read_file(&path)?;
+
+    serde_yaml::from_slice(&content)
+        .map_err(|yaml_err| Error::from(ErrorKind::StateFileParseFailed { yaml_err, path }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{
+        fake::{FakeEntry, FakeSystem},
+        FilesystemApi,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn save_and_restore() {
+        let mut sys = FakeSystem::new()
+            .with_file(
+                "/home/.tmux.conf",
+                "hand edited",
+                FilePermission::UnixMode(0o600),
+            )
+            .with_symbolic_link("/somewhere/config.toml", "/home/config.toml");
+        let mut store = BackupStore::new("/state");
+
+        store.save(&mut sys, "/home/.tmux.conf").unwrap();
+        store.save(&mut sys, "/home/config.toml").unwrap();
+        store.save(&mut sys, "/home/not_exists").unwrap();
+
+        let backups = store.list(&sys).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            backups[0].manifest.entries,
+            vec![
+                BackupEntry {
+                    path: PathBuf::from("/home/.tmux.conf"),
+                    kind: BackupKind::File {
+                        mode: "0600".to_owned(),
+                        content: "0".to_owned(),
+                    },
+                },
+                BackupEntry {
+                    path: PathBuf::from("/home/config.toml"),
+                    kind: BackupKind::SymbolicLink {
+                        original: PathBuf::from("/somewhere/config.toml"),
+                    },
+                },
+            ]
+        );
+
+        // overwrite then roll back.
+        sys.create_file(
+            "/home/.tmux.conf",
+            &b"managed"[..],
+            FilePermission::UnixMode(0o644),
+        )
+        .unwrap();
+        sys.create_symbolic_link("/dotfiles/config.toml", "/home/config.toml")
+            .unwrap();
+
+        let mut store = BackupStore::new("/state");
+        store.restore(&mut sys, &backups[0].id, false).unwrap();
+
+        assert_eq!(
+            sys.entry("/home/.tmux.conf"),
+            Some(&FakeEntry::File {
+                content: b"hand edited".to_vec(),
+                permission: FilePermission::UnixMode(0o600),
+            })
+        );
+        assert_eq!(
+            sys.entry("/home/config.toml"),
+            Some(&FakeEntry::SymbolicLink {
+                original: PathBuf::from("/somewhere/config.toml"),
+            })
+        );
+        // restore itself is backed up.
+        assert_eq!(store.list(&sys).unwrap().len(), 2);
+        assert!(matches!(
+            store
+                .restore(&mut sys, "unknown", false)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::BackupNotFound { .. }
+        ));
+    }
+}
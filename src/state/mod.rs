@@ -0,0 +1,37 @@
+mod backup;
+pub use backup::{Backup, BackupKind, BackupStore};
+
+mod state;
+pub use state::{ManagedEntry, ManagedKind, ManagedPackage, State, STATE_VERSION};
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+
+/// Overrides the state directory.
+const STATE_DIR_ENV: &str = "LOCALENV_STATE_DIR";
+
+/// Directory where localenv keeps data about past runs such as backups.
+/// `$LOCALENV_STATE_DIR`, `$XDG_STATE_HOME/localenv` or `$HOME/.local/state/localenv`.
+pub fn state_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os(STATE_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::var_os("XDG_STATE_HOME") {
+        return Ok(Path::new(&dir).join("localenv"));
+    }
+    match env::var_os("HOME") {
+        Some(home) => Ok(Path::new(&home).join(".local/state/localenv")),
+        None => Err(Error::from(ErrorKind::StateDirUndefined)),
+    }
+}
+
+/// Hex encoded sha256 of content, used to detect changes.
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
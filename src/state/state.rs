@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+
+use std::path::{Path, PathBuf};
+
+use crate::{prelude::*, system};
+
+const STATE_FILE: &str = "state.yaml";
+/// Value of `version` this binary reads and writes.
+pub const STATE_VERSION: u32 = 1;
+const STATE_FILE_PERMISSION: system::FilePermission = system::FilePermission::UnixMode(0o600);
+const STATE_DIR_PERMISSION: system::FilePermission = system::FilePermission::UnixMode(0o700);
+
+/// What localenv manages on this machine, recorded by apply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct State {
+    pub version: u32,
+    /// Revision of the config last applied.
+    pub config_revision: Option<String>,
+    #[serde(default)]
+    pub filesystem: Vec<ManagedEntry>,
+    #[serde(default)]
+    pub packages: Vec<ManagedPackage>,
+}
+
+/// Filesystem entry written by localenv.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManagedEntry {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub kind: ManagedKind,
+    /// Whether localenv created the entry rather than overwrote an existing one.
+    pub created: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ManagedKind {
+    File { content_hash: String, mode: String },
+    SymbolicLink { original: PathBuf },
+    Directory { mode: String },
+}
+
+/// Package installed through an installer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManagedPackage {
+    pub installer: String,
+    pub package: String,
+    pub version: Option<String>,
+    /// Install root given by `--root`. None for installer's default root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+    /// Whether localenv installed the package rather than found it installed.
+    pub created: bool,
+}
+
+/// Version read ahead of the rest, which may be of other format.
+#[derive(Deserialize)]
+struct StateVersion {
+    version: u32,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            config_revision: None,
+            filesystem: Vec::new(),
+            packages: Vec::new(),
+        }
+    }
+}
+
+impl State {
+    /// Load state file from state directory. Empty state if not exists yet.
+    pub fn load<Api>(system: &Api, state_dir: impl AsRef<Path>) -> Result<Self>
+    where
+        Api: system::Api,
+    {
+        let path = state_dir.as_ref().join(STATE_FILE);
+        if system.file_type(&path)?.is_none() {
+            debug!("{} not found", path.display());
+            return Ok(State::default());
+        }
+
+        let content = system.read_file(&path)?;
+        let parse_err =
+            |yaml_err, path| Error::from(ErrorKind::StateFileParseFailed { yaml_err, path });
+        let StateVersion { version } =
+            serde_yaml::from_slice(&content).map_err(|err| parse_err(err, path.clone()))?;
+        if version != STATE_VERSION {
+            return Err(Error::from(ErrorKind::UnsupportedStateVersion {
+                version,
+                path,
+            }));
+        }
+        serde_yaml::from_slice(&content).map_err(|err| parse_err(err, path))
+    }
+
+    pub fn save<Api>(&self, system: &mut Api, state_dir: impl AsRef<Path>) -> Result<()>
+    where
+        Api: system::Api,
+    {
+        let state_dir = state_dir.as_ref();
+        if system.file_type(state_dir)?.is_none() {
+            system.create_dir(state_dir, STATE_DIR_PERMISSION, true)?;
+        }
+
+        let yaml = serde_yaml::to_string(self)
+            .map_err(|err| ErrorKind::Internal(format!("serialize state: {}", err)))?;
+
+        system.create_file(
+            state_dir.join(STATE_FILE),
+            yaml.as_bytes(),
+            STATE_FILE_PERMISSION,
+        )
+    }
+
+    /// Record written entry. Entry once created by localenv stays marked as created.
+    pub fn upsert_entry(&mut self, mut entry: ManagedEntry) {
+        match self.filesystem.iter_mut().find(|e| e.path == entry.path) {
+            Some(existing) => {
+                entry.created |= existing.created;
+                *existing = entry;
+            }
+            None => self.filesystem.push(entry),
+        }
+    }
+
+    pub fn remove_entry(&mut self, path: impl AsRef<Path>) {
+        self.filesystem.retain(|entry| entry.path != path.as_ref());
+    }
+
+    /// Record installed package. Package once installed by localenv stays marked as created.
+    /// Packages are told apart by installer, name and install root.
+    pub fn upsert_package(&mut self, mut package: ManagedPackage) {
+        match self.packages.iter_mut().find(|p| {
+            p.is(
+                &package.installer,
+                &package.package,
+                package.root.as_deref(),
+            )
+        }) {
+            Some(existing) => {
+                package.created |= existing.created;
+                if package.version.is_none() {
+                    package.version = existing.version.take();
+                }
+                *existing = package;
+            }
+            None => self.packages.push(package),
+        }
+    }
+
+    pub fn remove_package(&mut self, installer: &str, package: &str, root: Option<&Path>) {
+        self.packages.retain(|p| !p.is(installer, package, root));
+    }
+}
+
+impl ManagedPackage {
+    /// Whether this is given package installed by given installer under given root.
+    pub fn is(&self, installer: &str, package: &str, root: Option<&Path>) -> bool {
+        self.installer == installer && self.package == package && self.root.as_deref() == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::fake::FakeSystem;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn save_and_load() {
+        let mut sys = FakeSystem::new();
+        assert_eq!(State::load(&sys, "/state").unwrap(), State::default());
+
+        let mut state = State {
+            config_revision: Some("abc".to_owned()),
+            ..Default::default()
+        };
+        state.upsert_entry(ManagedEntry {
+            path: PathBuf::from("/home/.tmux.conf"),
+            kind: ManagedKind::File {
+                content_hash: "1".to_owned(),
+                mode: "0644".to_owned(),
+            },
+            created: true,
+        });
+        state.upsert_entry(ManagedEntry {
+            path: PathBuf::from("/home/.tmux.conf"),
+            kind: ManagedKind::File {
+                content_hash: "2".to_owned(),
+                mode: "0644".to_owned(),
+            },
+            created: false,
+        });
+        state.upsert_package(ManagedPackage {
+            installer: "cargo".to_owned(),
+            package: "bat".to_owned(),
+            version: Some("0.18.0".to_owned()),
+            root: None,
+            created: true,
+        });
+        state.upsert_package(ManagedPackage {
+            installer: "cargo".to_owned(),
+            package: "bat".to_owned(),
+            version: None,
+            root: None,
+            created: false,
+        });
+        state.save(&mut sys, "/state").unwrap();
+
+        let loaded = State::load(&sys, "/state").unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.filesystem.len(), 1);
+        assert!(loaded.filesystem[0].created);
+        assert_eq!(loaded.packages[0].version, Some("0.18.0".to_owned()));
+        assert!(loaded.packages[0].created);
+    }
+
+    #[test]
+    fn packages_under_other_root() {
+        let bat = |root: Option<&str>| ManagedPackage {
+            installer: "cargo".to_owned(),
+            package: "bat".to_owned(),
+            version: Some("0.18.0".to_owned()),
+            root: root.map(PathBuf::from),
+            created: true,
+        };
+        let mut state = State::default();
+        state.upsert_package(bat(None));
+        state.upsert_package(bat(Some("/opt/cargo")));
+        assert_eq!(state.packages, [bat(None), bat(Some("/opt/cargo"))]);
+
+        state.remove_package("cargo", "bat", Some(Path::new("/opt/cargo")));
+        assert_eq!(state.packages, [bat(None)]);
+    }
+
+    #[test]
+    fn reject_unsupported_version() {
+        let sys = FakeSystem::new().with_file(
+            "/state/state.yaml",
+            "version: 2\nlayout: other\n",
+            system::FilePermission::UnixMode(0o600),
+        );
+        assert!(matches!(
+            State::load(&sys, "/state").unwrap_err().kind(),
+            ErrorKind::UnsupportedStateVersion { version: 2, path }
+                if path == Path::new("/state/state.yaml")
+        ));
+    }
+}
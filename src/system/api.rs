@@ -1,19 +1,59 @@
-use std::{fmt, io, path::Path};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     prelude::*,
-    system::{FilePermission, Os},
+    system::{Command, CommandOutput, FilePermission, FileType, Os},
 };
 
 pub trait Api: FilesystemApi + CommandApi {
     fn os(&self) -> Os;
 
+    fn hostname(&self) -> Result<String>;
+
+    /// Name of user running localenv.
+    fn username(&self) -> String;
+
+    /// Architecture localenv is built for. e.g. `x86_64`, `aarch64`
+    fn arch(&self) -> &str;
+
+    /// Value of env var. None if it is not defined or not unicode.
+    fn env_var(&self, name: &str) -> Option<String>;
+
+    /// Path of binary found in $PATH.
+    fn binary_path(&self, name: &str) -> Option<PathBuf>;
+
     fn display<D>(&self, msg: D)
     where
         D: fmt::Display;
 }
 
 pub trait FilesystemApi {
+    /// Return type of entry at given path, or None if it does not exist.
+    fn file_type<P>(&self, path: P) -> Result<Option<FileType>>
+    where
+        P: AsRef<Path>;
+
+    fn read_file<P>(&self, path: P) -> Result<Vec<u8>>
+    where
+        P: AsRef<Path>;
+
+    /// Return target of symbolic link.
+    fn read_link<P>(&self, path: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>;
+
+    fn permission<P>(&self, path: P) -> Result<FilePermission>
+    where
+        P: AsRef<Path>;
+
+    /// Return paths of entries in given directory.
+    fn read_dir<P>(&self, path: P) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>;
+
     fn create_file<P, R>(&mut self, dest: P, content: R, permission: FilePermission) -> Result<()>
     where
         P: AsRef<Path>,
@@ -23,6 +63,19 @@ pub trait FilesystemApi {
     where
         P: AsRef<Path>,
         Q: AsRef<Path>;
+
+    /// Remove file or symbolic link. Link target is not affected.
+    fn remove_file<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>;
+
+    /// Create directory. Missing parents are created too if recursive.
+    fn create_dir<P>(&mut self, path: P, permission: FilePermission, recursive: bool) -> Result<()>
+    where
+        P: AsRef<Path>;
 }
 
-pub trait CommandApi {}
+pub trait CommandApi {
+    /// Run given command to completion and return its captured output.
+    async fn run_command(&self, cmd: &Command) -> Result<CommandOutput>;
+}
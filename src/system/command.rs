@@ -1,10 +1,123 @@
-use std::{ffi::OsStr, path::PathBuf};
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use crate::{prelude::*, system::CommandApi};
+use crate::prelude::*;
 
-pub struct Command {}
+/// Command to be executed through `CommandApi`.
+#[derive(Debug, Clone)]
+pub struct Command {
+    program: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    current_dir: Option<PathBuf>,
+    timeout: Option<Duration>,
+    stream: bool,
+}
+
+impl Command {
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        Self {
+            program: program.as_ref().to_owned(),
+            args: Vec::new(),
+            envs: Vec::new(),
+            current_dir: None,
+            timeout: None,
+            stream: false,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.envs
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    pub fn current_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.current_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Kill the process if it does not exit within given duration.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Display output lines while running in addition to capturing them.
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    pub fn program(&self) -> &OsStr {
+        self.program.as_os_str()
+    }
+
+    pub fn get_args(&self) -> &[OsString] {
+        self.args.as_slice()
+    }
 
-impl CommandApi for Command {}
+    pub fn get_envs(&self) -> &[(OsString, OsString)] {
+        self.envs.as_slice()
+    }
+
+    pub fn get_current_dir(&self) -> Option<&Path> {
+        self.current_dir.as_deref()
+    }
+
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn is_stream(&self) -> bool {
+        self.stream
+    }
+}
+
+/// Captured result of executed command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandOutput {
+    /// Exit code. None if the process was terminated by signal.
+    pub code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// Convert non-zero exit into error.
+    pub fn ensure_success(self, cmd: &Command) -> Result<Self> {
+        if self.success() {
+            Ok(self)
+        } else {
+            Err(Error::from(ErrorKind::CommandFailed {
+                name: cmd.program().to_owned(),
+                code: self.code,
+            }))
+        }
+    }
+}
 
 pub fn resolve_binary_path(path: impl AsRef<OsStr>) -> Result<PathBuf> {
     which::which(&path).map_err(|err| {
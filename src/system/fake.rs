@@ -0,0 +1,494 @@
+//! In-memory `Api` implementation for hermetic plan/apply tests.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    prelude::*,
+    system::{self, Command, CommandOutput, FilePermission, FileType, Os},
+};
+
+/// Node in the virtual filesystem tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FakeEntry {
+    File {
+        content: Vec<u8>,
+        permission: FilePermission,
+    },
+    SymbolicLink {
+        original: PathBuf,
+    },
+    Directory {
+        permission: FilePermission,
+    },
+}
+
+/// Api call recorded by `FakeSystem`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    CreateFile {
+        dest: PathBuf,
+        permission: FilePermission,
+    },
+    CreateSymbolicLink {
+        original: PathBuf,
+        link: PathBuf,
+    },
+    RemoveFile {
+        path: PathBuf,
+    },
+    CreateDirectory {
+        path: PathBuf,
+        permission: FilePermission,
+        recursive: bool,
+    },
+    RunCommand {
+        program: OsString,
+        args: Vec<OsString>,
+    },
+    Display(String),
+}
+
+pub struct FakeSystem {
+    os: Os,
+    hostname: String,
+    username: String,
+    arch: String,
+    envs: BTreeMap<String, String>,
+    entries: BTreeMap<PathBuf, FakeEntry>,
+    commands: Vec<(Vec<OsString>, CommandOutput)>,
+    calls: Mutex<Vec<Call>>,
+}
+
+impl FakeSystem {
+    pub fn new() -> Self {
+        Self {
+            os: Os::Linux,
+            hostname: "localhost".to_owned(),
+            username: "user".to_owned(),
+            arch: "x86_64".to_owned(),
+            envs: BTreeMap::new(),
+            entries: BTreeMap::new(),
+            commands: Vec::new(),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_os(mut self, os: Os) -> Self {
+        self.os = os;
+        self
+    }
+
+    pub fn with_hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = hostname.into();
+        self
+    }
+
+    pub fn with_username(mut self, username: impl Into<String>) -> Self {
+        self.username = username.into();
+        self
+    }
+
+    pub fn with_arch(mut self, arch: impl Into<String>) -> Self {
+        self.arch = arch.into();
+        self
+    }
+
+    /// Define env var. No env var is defined by default, unlike process env.
+    pub fn with_env(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.envs.insert(name.into(), value.into());
+        self
+    }
+
+    /// Put file into the virtual filesystem.
+    pub fn with_file(
+        mut self,
+        path: impl Into<PathBuf>,
+        content: impl Into<Vec<u8>>,
+        permission: FilePermission,
+    ) -> Self {
+        self.entries.insert(
+            path.into(),
+            FakeEntry::File {
+                content: content.into(),
+                permission,
+            },
+        );
+        self
+    }
+
+    /// Put directory into the virtual filesystem.
+    pub fn with_directory(mut self, path: impl Into<PathBuf>, permission: FilePermission) -> Self {
+        self.entries
+            .insert(path.into(), FakeEntry::Directory { permission });
+        self
+    }
+
+    /// Put symbolic link into the virtual filesystem.
+    pub fn with_symbolic_link(
+        mut self,
+        original: impl Into<PathBuf>,
+        link: impl Into<PathBuf>,
+    ) -> Self {
+        self.entries.insert(
+            link.into(),
+            FakeEntry::SymbolicLink {
+                original: original.into(),
+            },
+        );
+        self
+    }
+
+    /// Respond with given output when command line (program followed by args) is run.
+    /// Running a command without scripted response is an error.
+    pub fn with_command_output(mut self, command_line: &[&str], output: CommandOutput) -> Self {
+        self.commands
+            .push((command_line.iter().map(OsString::from).collect(), output));
+        self
+    }
+
+    pub fn entry(&self, path: impl AsRef<Path>) -> Option<&FakeEntry> {
+        self.entries.get(path.as_ref())
+    }
+
+    pub fn entries(&self) -> &BTreeMap<PathBuf, FakeEntry> {
+        &self.entries
+    }
+
+    /// Recorded calls in invocation order.
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Recorded command lines in invocation order.
+    pub fn command_lines(&self) -> Vec<Vec<OsString>> {
+        self.calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                Call::RunCommand { program, mut args } => {
+                    args.insert(0, program);
+                    Some(args)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn record(&self, call: Call) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    /// Ancestors of any entry are treated as existing directories.
+    fn lookup(&self, path: &Path) -> Option<FileType> {
+        match self.entries.get(path) {
+            Some(FakeEntry::File { .. }) => Some(FileType::File),
+            Some(FakeEntry::SymbolicLink { .. }) => Some(FileType::SymbolicLink),
+            Some(FakeEntry::Directory { .. }) => Some(FileType::Directory),
+            None if self.entries.keys().any(|entry| entry.starts_with(path)) => {
+                Some(FileType::Directory)
+            }
+            None => None,
+        }
+    }
+
+    fn not_found<T>(path: &Path) -> Result<T> {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("fake system: {} not found", path.display()),
+        )
+        .into())
+    }
+
+    /// Fail like the real filesystem when parent directory does not exist.
+    fn ensure_parent_dir(&self, path: &Path) -> Result<()> {
+        match path.parent() {
+            Some(parent) if self.lookup(parent) != Some(FileType::Directory) => {
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("fake system: {} is not a directory", parent.display()),
+                )
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl system::Api for FakeSystem {
+    fn os(&self) -> Os {
+        self.os
+    }
+
+    fn hostname(&self) -> Result<String> {
+        Ok(self.hostname.clone())
+    }
+
+    fn username(&self) -> String {
+        self.username.clone()
+    }
+
+    fn arch(&self) -> &str {
+        &self.arch
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.envs.get(name).cloned()
+    }
+
+    /// Look up files in directories of fake `PATH` env.
+    fn binary_path(&self, name: &str) -> Option<PathBuf> {
+        self.envs
+            .get("PATH")?
+            .split(':')
+            .map(|dir| Path::new(dir).join(name))
+            .find(|path| matches!(self.entries.get(path), Some(FakeEntry::File { .. })))
+    }
+
+    fn display<D>(&self, msg: D)
+    where
+        D: fmt::Display,
+    {
+        self.record(Call::Display(msg.to_string()));
+    }
+}
+
+impl system::FilesystemApi for FakeSystem {
+    fn file_type<P>(&self, path: P) -> Result<Option<FileType>>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(self.lookup(path.as_ref()))
+    }
+
+    fn read_file<P>(&self, path: P) -> Result<Vec<u8>>
+    where
+        P: AsRef<Path>,
+    {
+        match self.entries.get(path.as_ref()) {
+            Some(FakeEntry::File { content, .. }) => Ok(content.clone()),
+            _ => FakeSystem::not_found(path.as_ref()),
+        }
+    }
+
+    fn read_link<P>(&self, path: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        match self.entries.get(path.as_ref()) {
+            Some(FakeEntry::SymbolicLink { original }) => Ok(original.clone()),
+            _ => FakeSystem::not_found(path.as_ref()),
+        }
+    }
+
+    fn permission<P>(&self, path: P) -> Result<FilePermission>
+    where
+        P: AsRef<Path>,
+    {
+        match self.entries.get(path.as_ref()) {
+            Some(FakeEntry::File { permission, .. })
+            | Some(FakeEntry::Directory { permission }) => Ok(*permission),
+            _ if self.lookup(path.as_ref()).is_some() => Ok(FilePermission::UnixMode(0o755)),
+            _ => FakeSystem::not_found(path.as_ref()),
+        }
+    }
+
+    fn read_dir<P>(&self, path: P) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if self.lookup(path) != Some(FileType::Directory) {
+            return FakeSystem::not_found(path);
+        }
+
+        let mut children = self
+            .entries
+            .keys()
+            .filter_map(|entry| {
+                entry
+                    .strip_prefix(path)
+                    .ok()
+                    .and_then(|rel| rel.components().next())
+                    .map(|child| path.join(child))
+            })
+            .collect::<Vec<_>>();
+        children.dedup();
+        Ok(children)
+    }
+
+    fn remove_file<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        self.record(Call::RemoveFile { path: path.clone() });
+
+        match self.entries.get(&path) {
+            Some(FakeEntry::File { .. }) | Some(FakeEntry::SymbolicLink { .. }) => {
+                self.entries.remove(&path);
+                Ok(())
+            }
+            _ => FakeSystem::not_found(&path),
+        }
+    }
+
+    fn create_file<P, R>(
+        &mut self,
+        dest: P,
+        mut content: R,
+        permission: FilePermission,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+        R: io::Read,
+    {
+        let dest = dest.as_ref().to_path_buf();
+        self.record(Call::CreateFile {
+            dest: dest.clone(),
+            permission,
+        });
+        self.ensure_parent_dir(&dest)?;
+
+        let mut buf = Vec::new();
+        content.read_to_end(&mut buf)?;
+        self.entries.insert(
+            dest,
+            FakeEntry::File {
+                content: buf,
+                permission,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn create_symbolic_link<P, Q>(&mut self, original: P, link: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let original = original.as_ref().to_path_buf();
+        let link = link.as_ref().to_path_buf();
+        self.record(Call::CreateSymbolicLink {
+            original: original.clone(),
+            link: link.clone(),
+        });
+        self.ensure_parent_dir(&link)?;
+
+        self.entries
+            .insert(link, FakeEntry::SymbolicLink { original });
+
+        Ok(())
+    }
+
+    fn create_dir<P>(&mut self, path: P, permission: FilePermission, recursive: bool) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        self.record(Call::CreateDirectory {
+            path: path.clone(),
+            permission,
+            recursive,
+        });
+        if !recursive {
+            self.ensure_parent_dir(&path)?;
+        }
+
+        self.entries
+            .insert(path, FakeEntry::Directory { permission });
+
+        Ok(())
+    }
+}
+
+impl system::CommandApi for FakeSystem {
+    async fn run_command(&self, cmd: &Command) -> Result<CommandOutput> {
+        self.record(Call::RunCommand {
+            program: cmd.program().to_owned(),
+            args: cmd.get_args().to_vec(),
+        });
+
+        let command_line: Vec<&std::ffi::OsStr> = std::iter::once(cmd.program())
+            .chain(cmd.get_args().iter().map(OsString::as_os_str))
+            .collect();
+
+        match self.commands.iter().find(|(line, _)| {
+            line.iter()
+                .map(OsString::as_os_str)
+                .eq(command_line.iter().copied())
+        }) {
+            Some((_, output)) => Ok(output.clone()),
+            None => Err(Error::from(ErrorKind::Internal(format!(
+                "fake system: unexpected command {:?}",
+                command_line
+            )))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{Api, CommandApi, FilesystemApi};
+
+    #[tokio::test]
+    async fn record_calls() {
+        let mut sys = FakeSystem::new()
+            .with_directory("/home", FilePermission::UnixMode(0o755))
+            .with_command_output(
+                &["cargo", "install", "--list"],
+                CommandOutput {
+                    code: Some(0),
+                    ..Default::default()
+                },
+            );
+
+        sys.create_file("/home/a", &b"aaa"[..], FilePermission::UnixMode(0o644))
+            .unwrap();
+        sys.create_symbolic_link("/home/a", "/home/b").unwrap();
+        assert!(sys
+            .create_file("/home/x/a", &b""[..], FilePermission::UnixMode(0o644))
+            .is_err());
+        assert_eq!(sys.file_type("/").unwrap(), Some(FileType::Directory));
+        assert_eq!(sys.file_type("/home/x").unwrap(), None);
+        sys.display("hello");
+        let output = sys
+            .run_command(&Command::new("cargo").args(["install", "--list"]))
+            .await
+            .unwrap();
+        assert!(output.success());
+        assert!(sys.run_command(&Command::new("brew")).await.is_err());
+
+        assert_eq!(
+            sys.entry("/home/a"),
+            Some(&FakeEntry::File {
+                content: b"aaa".to_vec(),
+                permission: FilePermission::UnixMode(0o644),
+            })
+        );
+        assert_eq!(
+            sys.entry("/home/b"),
+            Some(&FakeEntry::SymbolicLink {
+                original: PathBuf::from("/home/a")
+            })
+        );
+        assert_eq!(sys.calls().len(), 6);
+        assert_eq!(
+            sys.command_lines(),
+            vec![
+                vec![
+                    OsString::from("cargo"),
+                    OsString::from("install"),
+                    OsString::from("--list")
+                ],
+                vec![OsString::from("brew")],
+            ]
+        );
+    }
+}
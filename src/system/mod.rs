@@ -7,8 +7,13 @@ pub use system::System;
 mod os;
 pub use os::Os;
 
+#[cfg(test)]
+pub mod fake;
+
 mod command;
-pub use command::{resolve_binary_path, Command};
+pub use command::{resolve_binary_path, Command, CommandOutput};
+
+use std::fmt;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FilePermission {
@@ -18,3 +23,20 @@ pub enum FilePermission {
     #[allow(dead_code)]
     Windows(),
 }
+
+impl fmt::Display for FilePermission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilePermission::UnixMode(mode) => write!(f, "{:04o}", mode),
+            FilePermission::Windows() => write!(f, "windows"),
+        }
+    }
+}
+
+/// Type of existing filesystem entry. Symbolic links are not followed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType {
+    File,
+    Directory,
+    SymbolicLink,
+}
@@ -43,12 +43,12 @@ impl Os {
     }
 
     #[cfg(target_os = "windows")]
-    fn detect() -> Os {
+    pub(super) fn detect() -> Os {
         Os::Windows
     }
 
     #[cfg(target_os = "linux")]
-    fn detect() -> Os {
+    pub(super) fn detect() -> Os {
         Os::Linux
     }
 }
@@ -1,8 +1,17 @@
-use std::{fmt, fs, io, path::Path};
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process,
+};
 
 use crate::{
     prelude::*,
-    system::{self, FilePermission, Os},
+    system::{self, Command, CommandOutput, FilePermission, FileType, Os},
 };
 
 pub struct System {
@@ -14,6 +23,27 @@ impl system::Api for System {
         self.os
     }
 
+    fn hostname(&self) -> Result<String> {
+        Ok(hostname::get()?.to_string_lossy().into_owned())
+    }
+
+    fn username(&self) -> String {
+        whoami::username()
+    }
+
+    // compile time target, since running CPU can not be told portably.
+    fn arch(&self) -> &str {
+        env::consts::ARCH
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        env::var(name).ok()
+    }
+
+    fn binary_path(&self, name: &str) -> Option<PathBuf> {
+        system::resolve_binary_path(name).ok()
+    }
+
     fn display<D>(&self, msg: D)
     where
         D: fmt::Display,
@@ -22,11 +52,31 @@ impl system::Api for System {
     }
 }
 
-impl<'a, T: system::Api> system::Api for &'a mut T {
+impl<T: system::Api> system::Api for &mut T {
     fn os(&self) -> Os {
         (**self).os()
     }
 
+    fn hostname(&self) -> Result<String> {
+        (**self).hostname()
+    }
+
+    fn username(&self) -> String {
+        (**self).username()
+    }
+
+    fn arch(&self) -> &str {
+        (**self).arch()
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        (**self).env_var(name)
+    }
+
+    fn binary_path(&self, name: &str) -> Option<PathBuf> {
+        (**self).binary_path(name)
+    }
+
     fn display<D>(&self, msg: D)
     where
         D: fmt::Display,
@@ -36,6 +86,67 @@ impl<'a, T: system::Api> system::Api for &'a mut T {
 }
 
 impl system::FilesystemApi for System {
+    fn file_type<P>(&self, path: P) -> Result<Option<FileType>>
+    where
+        P: AsRef<Path>,
+    {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) => {
+                let file_type = metadata.file_type();
+                Ok(Some(if file_type.is_symlink() {
+                    FileType::SymbolicLink
+                } else if file_type.is_dir() {
+                    FileType::Directory
+                } else {
+                    FileType::File
+                }))
+            }
+            Err(io_err) if io_err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(io_err) => Err(io_err.into()),
+        }
+    }
+
+    fn read_file<P>(&self, path: P) -> Result<Vec<u8>>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(fs::read(path)?)
+    }
+
+    fn read_link<P>(&self, path: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(fs::read_link(path)?)
+    }
+
+    #[cfg(target_family = "unix")]
+    fn permission<P>(&self, path: P) -> Result<FilePermission>
+    where
+        P: AsRef<Path>,
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(path)?.permissions().mode();
+        Ok(FilePermission::UnixMode(mode & 0o777))
+    }
+
+    fn read_dir<P>(&self, path: P) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()).map_err(Error::from))
+            .collect()
+    }
+
+    fn remove_file<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(fs::remove_file(path)?)
+    }
+
     #[cfg(target_family = "unix")]
     fn create_file<P, R>(
         &mut self,
@@ -77,7 +188,7 @@ impl system::FilesystemApi for System {
         P: AsRef<Path>,
         Q: AsRef<Path>,
     {
-        return match std::os::unix::fs::symlink(&original, &link) {
+        match std::os::unix::fs::symlink(&original, &link) {
             Ok(_) => Ok(()),
             Err(io_err) => {
                 if let io::ErrorKind::AlreadyExists = io_err.kind() {
@@ -89,11 +200,85 @@ impl system::FilesystemApi for System {
                 }
                 Err(io_err.into())
             }
+        }
+    }
+
+    #[cfg(target_family = "unix")]
+    fn create_dir<P>(&mut self, path: P, permission: FilePermission, recursive: bool) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+        let mode = match permission {
+            FilePermission::UnixMode(mode) => mode,
+            _ => return Error::internal("could not get unix file permission"),
         };
+
+        match fs::DirBuilder::new()
+            .recursive(recursive)
+            .mode(mode)
+            .create(&path)
+        {
+            Ok(_) => (),
+            Err(io_err)
+                if io_err.kind() == io::ErrorKind::AlreadyExists && path.as_ref().is_dir() =>
+            {
+                debug!("{} already exists", path.as_ref().display());
+            }
+            Err(io_err) => return Err(io_err.into()),
+        }
+
+        // set permission explicitly because mode at creation is masked by umask and ignored for existing directory.
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+
+        Ok(())
     }
 }
 
-impl<'a, T: system::Api> system::FilesystemApi for &'a mut T {
+impl<T: system::Api> system::FilesystemApi for &mut T {
+    fn file_type<P>(&self, path: P) -> Result<Option<FileType>>
+    where
+        P: AsRef<Path>,
+    {
+        (**self).file_type(path)
+    }
+
+    fn read_file<P>(&self, path: P) -> Result<Vec<u8>>
+    where
+        P: AsRef<Path>,
+    {
+        (**self).read_file(path)
+    }
+
+    fn read_link<P>(&self, path: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        (**self).read_link(path)
+    }
+
+    fn permission<P>(&self, path: P) -> Result<FilePermission>
+    where
+        P: AsRef<Path>,
+    {
+        (**self).permission(path)
+    }
+
+    fn read_dir<P>(&self, path: P) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        (**self).read_dir(path)
+    }
+
+    fn remove_file<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        (**self).remove_file(path)
+    }
+
     fn create_file<P, R>(&mut self, dest: P, content: R, permission: FilePermission) -> Result<()>
     where
         P: AsRef<Path>,
@@ -109,14 +294,165 @@ impl<'a, T: system::Api> system::FilesystemApi for &'a mut T {
     {
         (**self).create_symbolic_link(original, link)
     }
+
+    fn create_dir<P>(&mut self, path: P, permission: FilePermission, recursive: bool) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        (**self).create_dir(path, permission, recursive)
+    }
 }
 
-impl system::CommandApi for System {}
+impl system::CommandApi for System {
+    async fn run_command(&self, cmd: &Command) -> Result<CommandOutput> {
+        let program = system::resolve_binary_path(cmd.program())?;
+        debug!("run {} {:?}", program.display(), cmd.get_args());
+
+        let mut command = process::Command::new(&program);
+        command
+            .args(cmd.get_args())
+            .envs(cmd.get_envs().iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = cmd.get_current_dir() {
+            command.current_dir(dir);
+        }
 
-impl<'a, T: system::CommandApi> system::CommandApi for &'a mut T {}
+        let mut child = command.spawn()?;
+        let stdout = child.stdout.take().expect("stdout piped");
+        let stderr = child.stderr.take().expect("stderr piped");
+
+        let run = async {
+            tokio::join!(
+                self.read_output(stdout, cmd.is_stream()),
+                self.read_output(stderr, cmd.is_stream()),
+                child.wait(),
+            )
+        };
+        let (stdout, stderr, status) = match cmd.get_timeout() {
+            Some(timeout) => tokio::time::timeout(timeout, run).await.map_err(|_| {
+                Error::from(ErrorKind::CommandTimeout {
+                    name: cmd.program().to_owned(),
+                    timeout,
+                })
+            })?,
+            None => run.await,
+        };
+
+        Ok(CommandOutput {
+            code: status?.code(),
+            stdout: stdout?,
+            stderr: stderr?,
+        })
+    }
+}
+
+impl<T: system::CommandApi> system::CommandApi for &mut T {
+    async fn run_command(&self, cmd: &Command) -> Result<CommandOutput> {
+        (**self).run_command(cmd).await
+    }
+}
+
+impl<T: system::CommandApi> system::CommandApi for &T {
+    async fn run_command(&self, cmd: &Command) -> Result<CommandOutput> {
+        (**self).run_command(cmd).await
+    }
+}
 
 impl System {
     pub fn new() -> Self {
         Self { os: Os::detect() }
     }
+
+    /// Capture child process output, displaying each line if stream enabled.
+    async fn read_output<R>(&self, reader: R, stream: bool) -> Result<Vec<u8>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        use system::Api;
+
+        let mut reader = BufReader::new(reader);
+        let mut output = Vec::new();
+        loop {
+            let start = output.len();
+            if reader.read_until(b'\n', &mut output).await? == 0 {
+                break;
+            }
+            if stream {
+                let line = String::from_utf8_lossy(&output[start..]);
+                self.display(format_args!("    {}", line.trim_end()));
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{CommandApi, FilesystemApi};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn run_command_capture_output() {
+        let cmd = Command::new("sh").args(["-c", "echo hello; echo err >&2; exit 3"]);
+        let output = System::new().run_command(&cmd).await.unwrap();
+
+        assert_eq!(output.code, Some(3));
+        assert_eq!(output.stdout, b"hello\n");
+        assert_eq!(output.stderr, b"err\n");
+        assert!(output.ensure_success(&cmd).is_err());
+    }
+
+    #[tokio::test]
+    async fn run_command_with_env_and_current_dir() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let cmd = Command::new("sh")
+            .args(["-c", "echo $GREETING; pwd"])
+            .env("GREETING", "hello")
+            .current_dir(&dir);
+        let output = System::new().run_command(&cmd).await.unwrap();
+
+        assert!(output.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!("hello\n{}\n", dir.display())
+        );
+    }
+
+    #[test]
+    fn create_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("localenv-create-dir-{}", std::process::id()));
+        let path = root.join("a/b");
+        let mut sys = System::new();
+
+        assert!(sys
+            .create_dir(&path, FilePermission::UnixMode(0o700), false)
+            .is_err());
+        sys.create_dir(&path, FilePermission::UnixMode(0o700), true)
+            .unwrap();
+        // existing directory is not an error.
+        sys.create_dir(&path, FilePermission::UnixMode(0o750), false)
+            .unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o750
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_command_timeout() {
+        let cmd = Command::new("sleep")
+            .arg("10")
+            .timeout(Duration::from_millis(100));
+        let err = System::new().run_command(&cmd).await.unwrap_err();
+
+        assert!(matches!(err.kind(), ErrorKind::CommandTimeout { .. }));
+    }
 }